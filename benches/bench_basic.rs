@@ -9,7 +9,7 @@ fn bench_lzf_compression(c: &mut Criterion) {
     for size in data {
         let name = format!("size={}", size);
         group.bench_function(name, |b| {
-            let buffer = std::iter::repeat(0u8).take(size).collect::<Vec<_>>();
+            let buffer = vec![0u8; size];
             b.iter(|| lzf::compress(&buffer).unwrap());
         });
     }
@@ -19,7 +19,7 @@ fn bench_lzf_compression(c: &mut Criterion) {
     for size in data {
         let name = format!("size={}", size);
         group.bench_function(name, |b| {
-            let buffer = std::iter::repeat(17u8).take(size).collect::<Vec<_>>();
+            let buffer = vec![17u8; size];
             b.iter(|| lzf::compress(&buffer).unwrap());
         });
     }
@@ -39,6 +39,18 @@ fn bench_lzf_compression(c: &mut Criterion) {
     }
     group.finish();
 
+    let mut group = c.benchmark_group("lzf reuse");
+    for size in [64, 256, KB] {
+        let name = format!("size={}", size);
+        group.bench_function(name, |b| {
+            let buffer = vec![17u8; size];
+            let mut compressor = lzf::Compressor::new();
+            let mut out = vec![0; size];
+            b.iter(|| compressor.compress_into(&buffer, &mut out).unwrap());
+        });
+    }
+    group.finish();
+
     c.bench_function("lzf decompression", |b| {
         let lorem = [
             31, 76, 111, 114, 101, 109, 32, 105, 112, 115, 117, 109, 32, 100, 111, 108, 111, 114,
@@ -59,6 +71,48 @@ fn bench_lzf_compression(c: &mut Criterion) {
         ];
         b.iter(|| lzf::decompress(&lorem, 451).unwrap());
     });
+
+    // `compress` and `decompress` allocate and shrink their output buffer on every call.
+    let mut group = c.benchmark_group("lzf alloc");
+    for size in [KB, 64 * KB, 1024 * KB] {
+        let buffer: Vec<u8> = (0..size)
+            .map(|i| (i % 251) as u8 ^ (i >> 10) as u8)
+            .collect();
+        let compressed = lzf::compress(&buffer).unwrap();
+
+        group.bench_function(format!("compress/size={}", size), |b| {
+            b.iter(|| lzf::compress(&buffer).unwrap());
+        });
+        group.bench_function(format!("decompress/size={}", size), |b| {
+            b.iter(|| lzf::decompress(&compressed, size).unwrap());
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("lzf large");
+    group.sample_size(10);
+    for size in [16 * 1024 * KB, 64 * 1024 * KB] {
+        let buffer: Vec<u8> = (0..size)
+            .map(|i| (i % 251) as u8 ^ (i >> 10) as u8)
+            .collect();
+        group.bench_function(format!("compress/size={}", size), |b| {
+            b.iter(|| lzf::compress(&buffer).unwrap());
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("lzf decompression runs");
+    for size in [KB, 64 * KB, 1024 * KB] {
+        let name = format!("size={}", size);
+        group.bench_function(name, |b| {
+            // Short repeating patterns produce overlapping back-references.
+            let buffer: Vec<u8> = (0..size).map(|i| (i % 3) as u8).collect();
+            let compressed = lzf::compress(&buffer).unwrap();
+            let mut out = vec![0; size];
+            b.iter(|| lzf::decompress_into(&compressed, &mut out).unwrap());
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, bench_lzf_compression);
@@ -30,15 +30,17 @@ pub mod sys {
         let mut out : Vec<u8> = Vec::with_capacity(out_len);
 
         if data.len() == 0 {
-            return Err(LzfError::DataCorrupted);
+            return Err(LzfError::InputTooShort);
         }
 
         let result = unsafe { lzf_decompress(data.as_ptr() as *const c_void, data.len() as c_uint,
         out.as_ptr() as *const c_void, out_len as c_uint) };
         match result {
+            // liblzf only reports EINVAL for any corrupted data.
+            #[allow(deprecated)]
             0 => {
                 match Error::last_os_error().raw_os_error() {
-                    Some(7)  => Err(LzfError::BufferTooSmall),
+                    Some(7)  => Err(LzfError::OutputLimitExceeded),
                     Some(22) => Err(LzfError::DataCorrupted),
                     Some(e)  => Err(LzfError::UnknownError(e)),
                     None     => Err(LzfError::UnknownError(0)),
@@ -106,7 +108,7 @@ mod test {
 
         match sys::decompress(&compressed, 10) {
             Ok(_) => panic!("Decompression worked. That should not happen"),
-            Err(err) => assert_eq!(LzfError::BufferTooSmall, err)
+            Err(err) => assert_eq!(LzfError::OutputLimitExceeded, err)
         }
     }
 
@@ -116,13 +118,14 @@ mod test {
 
         match sys::decompress(lorem.as_bytes(), lorem.len()) {
             Ok(_) => panic!("Decompression worked. That should not happen"),
+            #[allow(deprecated)]
             Err(err) => assert_eq!(LzfError::DataCorrupted, err)
         }
     }
 
     #[test]
     fn test_empty() {
-        assert_eq!(LzfError::DataCorrupted, sys::decompress(&[], 10).unwrap_err());
+        assert_eq!(LzfError::InputTooShort, sys::decompress(&[], 10).unwrap_err());
     }
 }
 
@@ -136,7 +139,6 @@ mod quickcheck_test {
         let compr = match sys::compress(&data) {
             Ok(compr) => compr,
             Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
-            Err(LzfError::DataCorrupted) => return TestResult::discard(),
             e @ _ => panic!("{:?}", e),
         };
         let decompr = sys::decompress(&compr, data.len()).unwrap();
@@ -160,7 +162,12 @@ mod quickcheck_test {
     }
 
     fn compare_decompress(data: Vec<u8>) -> TestResult {
-        let rust_decompr = lzf::decompress(&data, data.len()*2);
+        #[allow(deprecated)]
+        let rust_decompr = lzf::decompress(&data, data.len()*2)
+            .map_err(|e| match e {
+                LzfError::CorruptReference(_) | LzfError::TruncatedInput(_) => LzfError::DataCorrupted,
+                e => e,
+            });
         let native_decompr = sys::decompress(&data, data.len()*2);
         TestResult::from_bool(rust_decompr == native_decompr)
     }
@@ -174,7 +181,6 @@ mod quickcheck_test {
         let compr = match sys::compress(&data) {
             Ok(compr) => compr,
             Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
-            Err(LzfError::DataCorrupted) => return TestResult::discard(),
             e @ _ => panic!("{:?}", e),
         };
 
@@ -186,7 +192,6 @@ mod quickcheck_test {
         let compr = match lzf::compress(&data) {
             Ok(compr) => compr,
             Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
-            Err(LzfError::DataCorrupted) => return TestResult::discard(),
             e @ _ => panic!("{:?}", e),
         };
 
@@ -0,0 +1,435 @@
+//! Asynchronous streaming compression in the block format of [`stream`](crate::stream).
+//!
+//! [`AsyncLzfReader`] and [`AsyncLzfWriter`] implement the `AsyncRead` and `AsyncWrite`
+//! traits of tokio (with the `tokio` feature) and of the `futures-io` crate
+//! (with the `futures-io` feature).
+//! The underlying reader or writer must be `Unpin`, use `Box::pin` for one that isn't.
+//!
+//! Example:
+//!
+//! ```rust
+//! # #[cfg(feature = "tokio")]
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! use lzf::async_stream::{AsyncLzfReader, AsyncLzfWriter};
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! let mut writer = AsyncLzfWriter::new(Vec::new());
+//! writer.write_all(b"aaaaaaaaaaaaaaaaaaaa").await.unwrap();
+//! writer.shutdown().await.unwrap();
+//! let framed = writer.into_inner();
+//!
+//! let mut reader = AsyncLzfReader::new(&framed[..]);
+//! let mut data = Vec::new();
+//! reader.read_to_end(&mut data).await.unwrap();
+//! assert_eq!(&b"aaaaaaaaaaaaaaaaaaaa"[..], &data[..]);
+//! # });
+//! ```
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use super::frame::TYPE1_HDR_SIZE;
+use super::stream::{decode_block, encode_block, unexpected_eof, BLOCK_SIZE};
+use super::Compressor;
+
+/// The longest framed block.
+const MAX_FRAMED_LEN: usize = TYPE1_HDR_SIZE + BLOCK_SIZE;
+
+/// Compresses data written to it and writes the framed blocks to the underlying writer.
+///
+/// Data is buffered until a full block is available.
+/// Flushing the writer writes out the current partial block,
+/// and shutting it down (or closing it) writes out the last one.
+/// Unlike [`stream::Writer`](crate::stream::Writer), dropping the writer
+/// loses any data not yet written.
+pub struct AsyncLzfWriter<W> {
+    inner: W,
+    compressor: Compressor,
+    block: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    out_len: usize,
+}
+
+impl<W> AsyncLzfWriter<W> {
+    /// Create a new writer, writing the compressed blocks to `inner`.
+    pub fn new(inner: W) -> AsyncLzfWriter<W> {
+        AsyncLzfWriter {
+            inner,
+            compressor: Compressor::new(),
+            block: Vec::with_capacity(BLOCK_SIZE),
+            out: vec![0; MAX_FRAMED_LEN],
+            out_pos: 0,
+            out_len: 0,
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing to it directly will corrupt the compressed stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Return the underlying writer.
+    ///
+    /// Any data not yet flushed is lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Unpin> AsyncLzfWriter<W> {
+    fn poll_write_with<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        write: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnMut(Pin<&mut W>, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        if self.block.len() == BLOCK_SIZE {
+            ready!(self.poll_write_blocks(cx, write))?;
+        }
+
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    /// Write out the pending framed block and the current partial block.
+    fn poll_write_blocks<F>(&mut self, cx: &mut Context<'_>, mut write: F) -> Poll<io::Result<()>>
+    where
+        F: FnMut(Pin<&mut W>, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        loop {
+            while self.out_pos < self.out_len {
+                let buf = &self.out[self.out_pos..self.out_len];
+                match ready!(write(Pin::new(&mut self.inner), cx, buf))? {
+                    0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                    n => self.out_pos += n,
+                }
+            }
+
+            if self.block.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            self.out_len = encode_block(&mut self.compressor, &self.block, &mut self.out)?;
+            self.out_pos = 0;
+            self.block.clear();
+        }
+    }
+}
+
+impl<W> fmt::Debug for AsyncLzfWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncLzfWriter")
+            .field(
+                "buffered",
+                &(self.block.len() + self.out_len - self.out_pos),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for AsyncLzfWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_write_with(cx, buf, tokio::io::AsyncWrite::poll_write)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_blocks(cx, tokio::io::AsyncWrite::poll_write))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_blocks(cx, tokio::io::AsyncWrite::poll_write))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<W: futures_io::AsyncWrite + Unpin> futures_io::AsyncWrite for AsyncLzfWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_write_with(cx, buf, futures_io::AsyncWrite::poll_write)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_blocks(cx, futures_io::AsyncWrite::poll_write))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_blocks(cx, futures_io::AsyncWrite::poll_write))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+/// Reads framed blocks from the underlying reader and yields the decompressed data.
+///
+/// Malformed input results in the same errors as with [`stream::Reader`](crate::stream::Reader).
+pub struct AsyncLzfReader<R> {
+    inner: R,
+    input: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl<R> AsyncLzfReader<R> {
+    /// Create a new reader, reading the compressed blocks from `inner`.
+    pub fn new(inner: R) -> AsyncLzfReader<R> {
+        AsyncLzfReader {
+            inner,
+            input: vec![0; MAX_FRAMED_LEN],
+            start: 0,
+            end: 0,
+            eof: false,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            pos: 0,
+        }
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly will corrupt the compressed stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Return the underlying reader.
+    ///
+    /// Any data read from it but not yet returned is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Unpin> AsyncLzfReader<R> {
+    /// Decode blocks until there is decompressed data to return or the stream ends.
+    fn poll_fill_block<F>(&mut self, cx: &mut Context<'_>, mut read: F) -> Poll<io::Result<()>>
+    where
+        F: FnMut(Pin<&mut R>, &mut Context<'_>, &mut [u8]) -> Poll<io::Result<usize>>,
+    {
+        // Loop, because a block might be empty.
+        while self.pos == self.block.len() {
+            if let Some(len) = decode_block(&self.input[self.start..self.end], &mut self.block)? {
+                self.start += len;
+                self.pos = 0;
+                continue;
+            }
+
+            if self.eof {
+                if self.start == self.end {
+                    break;
+                }
+                return Poll::Ready(Err(unexpected_eof("truncated block")));
+            }
+
+            // A whole block always fits into the input buffer.
+            self.input.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+
+            let buf = &mut self.input[self.end..];
+            match ready!(read(Pin::new(&mut self.inner), cx, buf))? {
+                0 => self.eof = true,
+                n => self.end += n,
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_read_with<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+        read: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnMut(Pin<&mut R>, &mut Context<'_>, &mut [u8]) -> Poll<io::Result<usize>>,
+    {
+        ready!(self.poll_fill_block(cx, read))?;
+
+        let available = &self.block[self.pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pos += len;
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<R> fmt::Debug for AsyncLzfReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncLzfReader")
+            .field("buffered", &(self.block.len() - self.pos))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for AsyncLzfReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let read = |inner: Pin<&mut R>, cx: &mut Context<'_>, buf: &mut [u8]| {
+            let mut buf = tokio::io::ReadBuf::new(buf);
+            ready!(inner.poll_read(cx, &mut buf))?;
+            Poll::Ready(Ok(buf.filled().len()))
+        };
+
+        let len = ready!(self
+            .get_mut()
+            .poll_read_with(cx, buf.initialize_unfilled(), read))?;
+        buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<R: futures_io::AsyncRead + Unpin> futures_io::AsyncRead for AsyncLzfReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_read_with(cx, buf, futures_io::AsyncRead::poll_read)
+    }
+}
+
+#[cfg(test)]
+const LOREM: &str = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy \
+                     eirmod tempor invidunt ut labore et dolore magna aliquyam erat, sed diam \
+                     voluptua. ";
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_tokio_roundtrip() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let data = LOREM.repeat(2000);
+
+    // A tiny pipe makes both sides wait for each other all the time.
+    let (client, server) = tokio::io::duplex(100);
+    let write = async {
+        let mut writer = AsyncLzfWriter::new(client);
+        for chunk in data.as_bytes().chunks(1000) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+    };
+    let read = async {
+        let mut decompressed = Vec::new();
+        AsyncLzfReader::new(server)
+            .read_to_end(&mut decompressed)
+            .await
+            .unwrap();
+        decompressed
+    };
+
+    let ((), decompressed) = tokio::join!(write, read);
+    assert_eq!(data.as_bytes(), &decompressed[..]);
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_tokio_flush_and_shutdown() {
+    use tokio::io::AsyncWriteExt;
+
+    let mut writer = AsyncLzfWriter::new(Vec::new());
+    writer.write_all(b"foo").await.unwrap();
+    assert!(writer.get_ref().is_empty());
+
+    writer.flush().await.unwrap();
+    assert_eq!(b"ZV\x00\x00\x03foo", &writer.get_ref()[..]);
+
+    writer.write_all(b"bar").await.unwrap();
+    writer.shutdown().await.unwrap();
+    assert_eq!(b"ZV\x00\x00\x03fooZV\x00\x00\x03bar", &writer.get_ref()[..]);
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_tokio_rejects_malformed() {
+    use tokio::io::AsyncReadExt;
+
+    let cases: &[(&[u8], io::ErrorKind)] = &[
+        (b"ZV\x00\x00", io::ErrorKind::UnexpectedEof),
+        (b"ZV\x00\x00\x04foo", io::ErrorKind::UnexpectedEof),
+        (b"ZX\x00\x00\x03foo", io::ErrorKind::InvalidData),
+        (b"ZV\x01\x00\x04\x00\x04\x02foo", io::ErrorKind::InvalidData),
+    ];
+
+    for (framed, kind) in cases {
+        let err = AsyncLzfReader::new(*framed)
+            .read_to_end(&mut Vec::new())
+            .await
+            .unwrap_err();
+        assert_eq!(*kind, err.kind(), "{:?}", framed);
+    }
+}
+
+#[cfg(feature = "futures-io")]
+#[test]
+fn test_futures_roundtrip() {
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    let data = LOREM.repeat(2000);
+
+    futures::executor::block_on(async {
+        let mut writer = AsyncLzfWriter::new(Vec::new());
+        writer.write_all(data.as_bytes()).await.unwrap();
+        writer.flush().await.unwrap();
+        writer.write_all(b"foo").await.unwrap();
+        writer.close().await.unwrap();
+        let framed = writer.into_inner();
+
+        let mut decompressed = Vec::new();
+        AsyncLzfReader::new(&framed[..])
+            .read_to_end(&mut decompressed)
+            .await
+            .unwrap();
+        assert_eq!((data + "foo").as_bytes(), &decompressed[..]);
+
+        // Both ends agree with the blocking implementation.
+        let mut expected = Vec::new();
+        std::io::Read::read_to_end(&mut crate::stream::Reader::new(&framed[..]), &mut expected)
+            .unwrap();
+        assert_eq!(expected, decompressed);
+    });
+}
@@ -0,0 +1,271 @@
+use alloc::{vec, vec::Vec};
+
+use super::compress::{compress_into_or_store, idx};
+use super::format::{MAX_LIT, MAX_OFF, MAX_REF};
+use super::output::Output;
+use super::{LzfError, LzfResult};
+
+const HLOG: u32 = 14;
+
+fn hash(p: &[u8], off: usize, hash_log: u32) -> usize {
+    idx(
+        (p[off] as u32) << 16 | (p[off + 1] as u32) << 8 | p[off + 2] as u32,
+        hash_log,
+    )
+}
+
+/// Compress the given data as small as possible.
+/// The return value will be set to the error if compression fails.
+///
+/// Instead of looking at a single earlier occurrence of the next bytes like
+/// [`compress`](crate::compress) does, this searches all occurrences within reach
+/// for the longest match, similar to `lzf_compress_best` from liblzf.
+/// This is a lot slower, but the output is usually smaller.
+/// It is decompressed with [`decompress`](crate::decompress) as usual.
+///
+/// The buffer is always set to the same size as the input buffer.
+/// If that is not enough to hold the lzf-compressed data,
+/// an error will be returned.
+///
+/// Example:
+///
+/// ```rust
+/// let data = "aaaaaaaaa";
+/// let compressed = lzf::compress_best(data.as_bytes()).unwrap();
+/// ```
+pub fn compress_best(data: &[u8]) -> LzfResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() + 1);
+    compress_best_with(
+        data,
+        &mut out,
+        data.len(),
+        &mut vec![0; 1 << HLOG],
+        &mut vec![0; MAX_OFF],
+    )?;
+
+    Ok(out)
+}
+
+/// Compress the given data as small as possible into the provided output buffer.
+/// On success the number of bytes written to `out` is returned.
+///
+/// See [`compress_best`] and [`compress_into`](crate::compress_into) for details.
+pub fn compress_best_into(data: &[u8], out: &mut [u8]) -> LzfResult<usize> {
+    compress_into_or_store(data, out, |out, out_buf_len| {
+        compress_best_with(
+            data,
+            out,
+            out_buf_len,
+            &mut vec![0; 1 << HLOG],
+            &mut vec![0; MAX_OFF],
+        )
+    })
+}
+
+/// `head` holds the most recent position (+1) of every hash value and has a power of two entries,
+/// `prev` the previous position (+1) with the same hash for every position within reach
+/// and has `MAX_OFF` entries.
+/// Just like the table of [`Compressor`](crate::Compressor), entries left over from
+/// previous inputs are checked before they are used, so the tables don't need to be cleared.
+pub(crate) fn compress_best_with<O: Output>(
+    data: &[u8],
+    out: &mut O,
+    out_buf_len: usize,
+    head: &mut [usize],
+    prev: &mut [usize],
+) -> LzfResult<()> {
+    debug_assert!(head.len().is_power_of_two() && prev.len() == MAX_OFF);
+    let hash_log = head.len().trailing_zeros();
+    let in_len = data.len();
+
+    if in_len < 2 || out_buf_len == 0 {
+        return Err(LzfError::NoCompressionPossible);
+    }
+
+    let insert = |head: &mut [usize], prev: &mut [usize], pos: usize| {
+        let h = hash(data, pos, hash_log);
+        prev[pos % MAX_OFF] = head[h];
+        head[h] = pos + 1;
+    };
+
+    out.skip(); /* start run by default */
+    let mut lit = 0;
+    let mut current_offset = 0;
+
+    while current_offset + 2 < in_len {
+        let maxlen = (in_len - current_offset).min(MAX_REF);
+
+        let mut best_len = 0;
+        let mut best_pos = 0;
+
+        let mut candidate = head[hash(data, current_offset, hash_log)];
+        while candidate > 0 {
+            let pos = candidate - 1;
+            // Left over from a previous input, or out of reach.
+            if pos >= current_offset || current_offset - pos > MAX_OFF {
+                break;
+            }
+
+            // A new match must be longer than the best one so far to qualify.
+            if data[pos + best_len] == data[current_offset + best_len] {
+                let len = data[pos..pos + maxlen]
+                    .iter()
+                    .zip(&data[current_offset..current_offset + maxlen])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if len > best_len {
+                    best_len = len;
+                    best_pos = pos;
+
+                    if len == maxlen {
+                        break;
+                    }
+                }
+            }
+
+            candidate = prev[pos % MAX_OFF];
+            if candidate > pos {
+                break;
+            }
+        }
+
+        insert(head, prev, current_offset);
+
+        if best_len >= 3 {
+            let off = current_offset - best_pos - 1;
+
+            /* the run is undone below if its length is zero */
+            if out.len() - usize::from(lit == 0) + 3 + 1 >= out_buf_len {
+                return Err(LzfError::NoCompressionPossible);
+            }
+
+            /* stop run */
+            out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
+            if lit == 0 {
+                out.truncate(out.len() - 1); /* undo run if length is zero */
+            }
+
+            let len = best_len - 2;
+            if len < 7 {
+                out.push((off >> 8) as u8 + (len << 5) as u8);
+            } else {
+                out.push((off >> 8) as u8 + (7 << 5));
+                out.push((len - 7) as u8);
+            }
+
+            out.push(off as u8);
+            out.skip(); /* start run */
+            lit = 0;
+
+            for pos in current_offset + 1..current_offset + best_len {
+                if pos + 2 >= in_len {
+                    break;
+                }
+                insert(head, prev, pos);
+            }
+            current_offset += best_len;
+        } else {
+            /* one more literal byte we must copy */
+            if out.len() >= out_buf_len {
+                return Err(LzfError::NoCompressionPossible);
+            }
+
+            lit += 1;
+            out.push(data[current_offset]);
+            current_offset += 1;
+
+            if lit == MAX_LIT {
+                /* stop run */
+                out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
+                lit = 0;
+                out.skip(); /* start run */
+            }
+        }
+    }
+
+    /* at most 3 bytes can be missing here */
+    if out.len() + 3 > out_buf_len {
+        return Err(LzfError::NoCompressionPossible);
+    }
+
+    while current_offset < in_len {
+        lit += 1;
+        out.push(data[current_offset]);
+        current_offset += 1;
+
+        if lit == MAX_LIT {
+            /* stop run */
+            out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
+            lit = 0;
+            out.skip(); /* start run */
+        }
+    }
+
+    /* end run */
+    out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
+    if lit == 0 {
+        out.truncate(out.len() - 1); /* undo run if length is zero */
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compress_best_lorem() {
+    use super::{compress, decompress};
+
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At \
+                 vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, \
+                 no sea takimata sanctus est Lorem ipsum dolor sit amet. Lorem ipsum dolor sit \
+                 amet, consetetur sadipscing elitr, sed diam nonumy eirmod tempor invidunt ut \
+                 labore et dolore magna aliquyam erat, sed diam voluptua.";
+
+    let compressed = compress_best(lorem.as_bytes()).unwrap();
+    assert!(compressed.len() < compress(lorem.as_bytes()).unwrap().len());
+
+    let decompressed = decompress(&compressed, lorem.len()).unwrap();
+    assert_eq!(lorem.as_bytes(), &decompressed[..]);
+}
+
+#[test]
+fn test_compress_best_long_runs() {
+    use super::decompress;
+
+    // Longer than the maximum match and the maximum offset.
+    let mut data = vec![0; 20_000];
+    data.extend((0..20_000).map(|i| (i % 251) as u8));
+
+    let compressed = compress_best(&data).unwrap();
+    let decompressed = decompress(&compressed, data.len()).unwrap();
+    assert_eq!(data, decompressed);
+}
+
+#[test]
+fn test_compress_best_skips_short() {
+    assert_eq!(
+        LzfError::NoCompressionPossible,
+        compress_best(b"foo").unwrap_err()
+    );
+    assert_eq!(
+        LzfError::NoCompressionPossible,
+        compress_best(&[0]).unwrap_err()
+    );
+}
+
+#[test]
+fn test_compress_best_into_short_buffer_after_literal_run() {
+    use super::decompress;
+
+    // A full literal run right before the first match.
+    let mut data: Vec<u8> = (100..129).collect();
+    data.extend(b"abcabcabcabc");
+
+    for len in 0..data.len() {
+        let mut out = vec![0; len];
+        if let Ok(out_len) = compress_best_into(&data, &mut out) {
+            assert_eq!(data, decompress(&out[..out_len], data.len()).unwrap());
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! The `lzf` command-line utility.
+//!
+//! Files are read and written in the block format of liblzf's `lzf` utility,
+//! see [`lzf::stream`], so both can be used interchangeably.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::process;
+
+use lzf::stream::{Reader, Writer};
+
+const USAGE: &str = "\
+usage: lzf <command> [-ckf] [file...]
+
+Without files, or with `-`, stdin is read and the result is written to stdout.
+
+commands:
+  compress, c      compress each file to file.lzf and remove the file
+  decompress, d    decompress each file.lzf to file and remove file.lzf
+  test, t          check the integrity of compressed files
+  list, l          list the sizes of compressed files
+
+options:
+  -c    write to stdout, keeping the input files
+  -k    keep the input files
+  -f    overwrite existing output files and write compressed data to a terminal
+  -h    print this help
+";
+
+const SUFFIX: &str = ".lzf";
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Command {
+    Compress,
+    Decompress,
+    Test,
+    List,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct Options {
+    command: Command,
+    stdout: bool,
+    keep: bool,
+    force: bool,
+    help: bool,
+    files: Vec<String>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let command = match args.next().as_deref() {
+        Some("compress" | "c") => Command::Compress,
+        Some("decompress" | "d") => Command::Decompress,
+        Some("test" | "t") => Command::Test,
+        Some("list" | "l") => Command::List,
+        Some("-h" | "--help" | "help") => {
+            return Ok(Options {
+                command: Command::Compress,
+                stdout: false,
+                keep: false,
+                force: false,
+                help: true,
+                files: Vec::new(),
+            })
+        }
+        Some(command) => return Err(format!("unknown command `{}`", command)),
+        None => return Err("missing command".to_string()),
+    };
+
+    let mut options = Options {
+        command,
+        stdout: false,
+        keep: false,
+        force: false,
+        help: false,
+        files: Vec::new(),
+    };
+
+    let mut only_files = false;
+    for arg in args {
+        if only_files || arg == "-" || !arg.starts_with('-') {
+            options.files.push(arg);
+        } else if arg == "--" {
+            only_files = true;
+        } else {
+            for flag in arg[1..].chars() {
+                match flag {
+                    'c' => options.stdout = true,
+                    'k' => options.keep = true,
+                    'f' => options.force = true,
+                    'h' => options.help = true,
+                    _ => return Err(format!("unknown option `-{}`", flag)),
+                }
+            }
+        }
+    }
+
+    if options.files.is_empty() {
+        options.files.push("-".to_string());
+    }
+
+    Ok(options)
+}
+
+fn open_input(file: &str) -> io::Result<Box<dyn Read>> {
+    if file == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(File::open(file)?))
+    }
+}
+
+fn create_output(path: &str, force: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    options.open(path).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => io::Error::new(
+            err.kind(),
+            format!("{} already exists, use -f to overwrite it", path),
+        ),
+        _ => err,
+    })
+}
+
+/// Compress or decompress a single file, or stdin.
+fn convert(options: &Options, file: &str) -> io::Result<()> {
+    let to_stdout = options.stdout || file == "-";
+    let compress = options.command == Command::Compress;
+
+    if compress && to_stdout && io::stdout().is_terminal() && !options.force {
+        return Err(io::Error::other(
+            "refusing to write compressed data to a terminal, use -f to force it",
+        ));
+    }
+
+    let output_path = if to_stdout {
+        None
+    } else if compress {
+        if file.ends_with(SUFFIX) {
+            return Err(io::Error::other(format!("already has {} suffix", SUFFIX)));
+        }
+        Some(format!("{}{}", file, SUFFIX))
+    } else {
+        match file.strip_suffix(SUFFIX) {
+            Some(path) if !path.is_empty() => Some(path.to_string()),
+            _ => {
+                return Err(io::Error::other(format!(
+                    "unknown suffix, expected {}",
+                    SUFFIX
+                )))
+            }
+        }
+    };
+
+    let mut input = open_input(file)?;
+    let output: Box<dyn Write> = match &output_path {
+        Some(path) => Box::new(create_output(path, options.force)?),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let result = if compress {
+        let mut writer = Writer::new(output);
+        io::copy(&mut input, &mut writer)
+            .and_then(|_| writer.finish())
+            .and_then(|mut output| output.flush())
+    } else {
+        let mut output = output;
+        io::copy(&mut Reader::new(input), &mut output).and_then(|_| output.flush())
+    };
+
+    match (result, output_path) {
+        (Ok(()), Some(_)) if !options.keep => fs::remove_file(file),
+        (Ok(()), _) => Ok(()),
+        (Err(err), Some(path)) => {
+            // Don't leave a partial output behind.
+            let _ = fs::remove_file(path);
+            Err(err)
+        }
+        (Err(err), None) => Err(err),
+    }
+}
+
+/// Counts the bytes read from the inner reader.
+struct Counter<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for Counter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// Check a file without decompressing it,
+/// returning the compressed and the uncompressed size.
+fn check(file: &str) -> io::Result<(u64, u64)> {
+    let mut input = Counter {
+        inner: open_input(file)?,
+        count: 0,
+    };
+    let uncompressed = lzf::stream::verify(&mut input)?;
+
+    Ok((input.count, uncompressed))
+}
+
+fn run(options: &Options, file: &str) -> io::Result<()> {
+    match options.command {
+        Command::Compress | Command::Decompress => convert(options, file),
+        Command::Test => lzf::stream::verify(open_input(file)?).map(|_| ()),
+        Command::List => {
+            let (compressed, uncompressed) = check(file)?;
+            let saved = match uncompressed {
+                0 => 0.0,
+                _ => 100.0 - compressed as f64 * 100.0 / uncompressed as f64,
+            };
+            println!(
+                "{:>12} {:>12} {:>6.1}% {}",
+                compressed, uncompressed, saved, file
+            );
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprint!("lzf: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
+
+    if options.command == Command::List {
+        println!(
+            "{:>12} {:>12} {:>7} name",
+            "compressed", "uncompressed", "saved"
+        );
+    }
+
+    let mut failed = false;
+    for file in &options.files {
+        if let Err(err) = run(&options, file) {
+            eprintln!("lzf: {}: {}", file, err);
+            failed = true;
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[test]
+fn test_parse_args() {
+    let parse = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+
+    let options = parse(&["d", "-kf", "a.lzf", "--", "-c"]).unwrap();
+    assert_eq!(Command::Decompress, options.command);
+    assert!(options.keep && options.force && !options.stdout);
+    assert_eq!(vec!["a.lzf", "-c"], options.files);
+
+    let options = parse(&["compress", "-c"]).unwrap();
+    assert!(options.stdout);
+    assert_eq!(vec!["-"], options.files);
+
+    assert!(parse(&["-h"]).unwrap().help);
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["unzip"]).is_err());
+    assert!(parse(&["c", "-x"]).is_err());
+}
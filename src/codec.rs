@@ -0,0 +1,244 @@
+//! A [`tokio_util::codec`] codec for LZF-compressed messages.
+//!
+//! Every message is framed as a 4-byte payload length, a 4-byte original length
+//! (both big-endian) and the payload.
+//! The payload is the lzf-compressed message,
+//! or the message itself if it can't be compressed, in which case both lengths are equal.
+//!
+//! Example:
+//!
+//! ```rust
+//! use bytes::BytesMut;
+//! use lzf::codec::LzfCodec;
+//! use tokio_util::codec::{Decoder, Encoder};
+//!
+//! let mut codec = LzfCodec::new();
+//! let mut buf = BytesMut::new();
+//! codec.encode(&b"aaaaaaaaaaaaaaaaaaaa"[..], &mut buf).unwrap();
+//!
+//! let message = codec.decode(&mut buf).unwrap().unwrap();
+//! assert_eq!(&b"aaaaaaaaaaaaaaaaaaaa"[..], &message[..]);
+//! ```
+
+use std::fmt;
+use std::io;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::format::max_decompressed_size;
+use super::{decompress_into, Compressor, LzfError};
+
+const HEADER_LEN: usize = 8;
+
+/// Encodes and decodes LZF-compressed messages.
+///
+/// Messages (and frames) longer than the maximum frame size are rejected
+/// with an error of kind `InvalidData`, before any memory is allocated for them.
+/// So are frames claiming a longer message than their payload can possibly expand to.
+pub struct LzfCodec {
+    compressor: Compressor,
+    max_frame_size: usize,
+}
+
+impl LzfCodec {
+    /// The default maximum frame size, 8 MiB.
+    pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Create a new codec with the default maximum frame size.
+    pub fn new() -> LzfCodec {
+        LzfCodec::with_max_frame_size(LzfCodec::DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Create a new codec accepting messages of up to `max_frame_size` bytes.
+    ///
+    /// Sizes above `u32::MAX` are clamped to `u32::MAX`, the largest length the frame can hold.
+    pub fn with_max_frame_size(max_frame_size: usize) -> LzfCodec {
+        LzfCodec {
+            compressor: Compressor::new(),
+            max_frame_size: max_frame_size.min(u32::MAX as usize),
+        }
+    }
+
+    /// The maximum length of a message.
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+}
+
+impl Default for LzfCodec {
+    fn default() -> LzfCodec {
+        LzfCodec::new()
+    }
+}
+
+impl fmt::Debug for LzfCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LzfCodec")
+            .field("max_frame_size", &self.max_frame_size)
+            .finish()
+    }
+}
+
+impl Decoder for LzfCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let payload_len = u32::from_be_bytes(src[..4].try_into().unwrap()) as usize;
+        let original_len = u32::from_be_bytes(src[4..8].try_into().unwrap()) as usize;
+
+        if original_len > self.max_frame_size {
+            return Err(invalid_data("frame exceeds the maximum frame size"));
+        }
+        if payload_len > original_len {
+            return Err(invalid_data("payload is longer than the original message"));
+        }
+        if original_len > max_decompressed_size(payload_len) {
+            return Err(invalid_data(
+                "payload is too short for the original message",
+            ));
+        }
+
+        if src.len() < HEADER_LEN + payload_len {
+            src.reserve(HEADER_LEN + payload_len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(HEADER_LEN);
+        let payload = src.split_to(payload_len);
+        if payload_len == original_len {
+            return Ok(Some(payload));
+        }
+
+        let mut message = BytesMut::zeroed(original_len);
+        match decompress_into(&payload, &mut message) {
+            Ok(len) if len == original_len => Ok(Some(message)),
+            Ok(_) => Err(invalid_data("payload is shorter than the original message")),
+            Err(LzfError::OutputLimitExceeded) => {
+                Err(invalid_data("payload is longer than the original message"))
+            }
+            Err(err) => Err(invalid_data(&format!("invalid payload: {}", err))),
+        }
+    }
+}
+
+impl Encoder<&[u8]> for LzfCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> io::Result<()> {
+        if item.len() > self.max_frame_size {
+            return Err(invalid_data("message exceeds the maximum frame size"));
+        }
+
+        let start = dst.len();
+        dst.reserve(HEADER_LEN + item.len());
+        dst.put_u32(0);
+        dst.put_u32(item.len() as u32);
+
+        // Only keep the compressed data if it is smaller.
+        let payload_len = match self.compressor.compress_to_bytes_mut(item, dst) {
+            Ok(len) => len,
+            Err(LzfError::NoCompressionPossible) => {
+                dst.extend_from_slice(item);
+                item.len()
+            }
+            Err(err) => return Err(io::Error::other(err)),
+        };
+
+        dst[start..start + 4].copy_from_slice(&(payload_len as u32).to_be_bytes());
+
+        Ok(())
+    }
+}
+
+impl Encoder<Bytes> for LzfCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> io::Result<()> {
+        self.encode(&item[..], dst)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[test]
+fn test_codec_roundtrip() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At \
+                 vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, \
+                 no sea takimata sanctus est Lorem ipsum dolor sit amet.";
+    let messages: [&[u8]; 4] = [b"", b"a", b"foo", lorem.as_bytes()];
+
+    let mut codec = LzfCodec::new();
+    let mut buf = BytesMut::new();
+    for message in messages {
+        codec.encode(message, &mut buf).unwrap();
+    }
+
+    // Stored messages have equal lengths.
+    assert_eq!(&b"\0\0\0\x03\0\0\0\x03foo"[..], &buf[17..28]);
+    assert!(buf.len() < 4 * HEADER_LEN + 4 + lorem.len());
+
+    // Feed the frames byte by byte.
+    let mut input = BytesMut::new();
+    let mut decoded = Vec::new();
+    for &byte in buf.iter() {
+        input.put_u8(byte);
+        while let Some(message) = codec.decode(&mut input).unwrap() {
+            decoded.push(message);
+        }
+    }
+
+    assert_eq!(&messages[..], &decoded[..]);
+    assert!(input.is_empty());
+}
+
+#[test]
+fn test_codec_max_frame_size() {
+    let mut codec = LzfCodec::with_max_frame_size(10);
+    let mut buf = BytesMut::new();
+
+    codec.encode(&[0; 10][..], &mut buf).unwrap();
+    let err = codec.encode(&[0; 11][..], &mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+    // A frame claiming a huge message is rejected from its header alone.
+    let mut huge = BytesMut::from(&b"\0\0\0\x10\xff\xff\xff\xff"[..]);
+    let err = codec.decode(&mut huge).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    assert!(huge.capacity() < 100);
+
+    // So is a frame claiming more than its payload can expand to.
+    let mut short = BytesMut::from(&b"\0\0\0\x01\0\x80\0\0\0"[..]);
+    let err = LzfCodec::new().decode(&mut short).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("too short"), "{}", err);
+}
+
+#[test]
+fn test_codec_rejects_malformed() {
+    let cases: &[&[u8]] = &[
+        // Payload longer than the message.
+        b"\0\0\0\x04\0\0\0\x03fooo",
+        // Compressed payload decompressing to fewer bytes.
+        b"\0\0\0\x03\0\0\0\x04\x01aa",
+        // Compressed payload decompressing to more bytes.
+        b"\0\0\0\x09\0\0\0\x64\x01aa\xe0\xbb\x00\x01aa",
+        // Back-reference before the start of the message.
+        b"\0\0\0\x02\0\0\0\x03\x20\x00",
+    ];
+
+    for case in cases {
+        let err = LzfCodec::new()
+            .decode(&mut BytesMut::from(*case))
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind(), "{:?}", case);
+    }
+}
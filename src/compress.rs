@@ -1,11 +1,19 @@
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+use core::cmp;
+
+#[cfg(feature = "bytes")]
+use bytes::BytesMut;
+
+#[cfg(feature = "alloc")]
+use super::best::compress_best_with;
+use super::format::{max_compressed_size, MAX_LIT, MAX_OFF, MAX_REF};
+#[cfg(feature = "bytes")]
+use super::output::BytesMutOutput;
+use super::output::{Output, SliceOutput};
 use super::{LzfError, LzfResult};
-use std::cmp;
 
-const HLOG: usize = 16;
-const HSIZE: u32 = 1 << HLOG;
-const MAX_OFF: usize = 1 << 13;
-const MAX_REF: usize = (1 << 8) + (1 << 3);
-const MAX_LIT: i32 = 1 << 5;
+const HLOG: u32 = 16;
 
 fn first(p: &[u8], off: usize) -> u32 {
     ((p[off] as u32) << 8) | p[off + 1] as u32
@@ -15,22 +23,14 @@ fn next(v: u32, p: &[u8], off: usize) -> u32 {
     (v << 8) | p[off + 2] as u32
 }
 
-fn idx(h: u32) -> usize {
+pub(crate) fn idx(h: u32, hlog: u32) -> usize {
     let h = h as u64;
     (
-        // 8 = 3*8-HLOG, but HLOG is constant at 16
-        (h.wrapping_shr(8).wrapping_sub(h * 5)) & (HSIZE - 1) as u64
+        // only the lower 3 bytes of h are part of the hash
+        (h.wrapping_shr(3 * 8 - hlog).wrapping_sub(h * 5)) & ((1 << hlog) - 1)
     ) as usize
 }
 
-fn not(i: i32) -> i32 {
-    if i == 0 {
-        1
-    } else {
-        0
-    }
-}
-
 /// Compress the given data, if possible.
 /// The return value will be set to the error if compression fails.
 ///
@@ -44,22 +44,446 @@ fn not(i: i32) -> i32 {
 /// let data = "aaaaaaaaa";
 /// let compressed = lzf::compress(data.as_bytes()).unwrap();
 /// ```
+#[cfg(feature = "alloc")]
 pub fn compress(data: &[u8]) -> LzfResult<Vec<u8>> {
-    let in_len = data.len();
-    let out_buf_len = in_len;
-    let mut out = vec![0; out_buf_len];
+    Compressor::new().compress(data)
+}
 
-    let mut out_len: i32 = 1; /* start run by default */
+/// Compress the given data into the provided output buffer, if possible.
+/// On success the number of bytes written to `out` is returned.
+///
+/// This mirrors `lzf_compress` from liblzf: the compressed data is never
+/// allowed to exceed the length of `out`.
+/// If the output buffer is not large enough to hold the lzf-compressed data,
+/// `NoCompressionPossible` is returned and the content of `out` is unspecified.
+///
+/// If `out` holds at least [`max_compressed_size`](crate::max_compressed_size)
+/// of the input length, data that can't be compressed is stored as literal runs instead,
+/// just like [`compress_always`] does, so only empty input fails.
+///
+/// Example:
+///
+/// ```rust
+/// let data = "aaaaaaaaa";
+/// let mut out = [0; 9];
+/// let len = lzf::compress_into(data.as_bytes(), &mut out).unwrap();
+/// let compressed = &out[..len];
+///
+/// let mut out = [0; lzf::max_compressed_size(3)];
+/// let len = lzf::compress_into(b"foo", &mut out).unwrap();
+/// assert_eq!(b"\x02foo", &out[..len]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn compress_into(data: &[u8], out: &mut [u8]) -> LzfResult<usize> {
+    Compressor::new().compress_into(data, out)
+}
 
-    let mut htab = vec![0; 1 << HLOG];
+/// Compress the given data and append it to `out`, if possible.
+/// On success the number of bytes appended is returned.
+///
+/// Just like [`compress`], the compressed data has to be shorter than the input,
+/// otherwise `NoCompressionPossible` is returned.
+/// On error `out` is left as it was.
+///
+/// Example:
+///
+/// ```rust
+/// use bytes::BytesMut;
+///
+/// let mut out = BytesMut::from(&b"header"[..]);
+/// let len = lzf::compress_to_bytes_mut(b"aaaaaaaaa", &mut out).unwrap();
+/// assert_eq!(6 + len, out.len());
+///
+/// let decompressed = lzf::decompress(&out[6..], 9).unwrap();
+/// assert_eq!(&b"aaaaaaaaa"[..], &decompressed[..]);
+/// ```
+#[cfg(feature = "bytes")]
+pub fn compress_to_bytes_mut(data: &[u8], out: &mut BytesMut) -> LzfResult<usize> {
+    Compressor::new().compress_to_bytes_mut(data, out)
+}
+
+/// Compress the given data with the given level, if possible.
+/// The return value will be set to the error if compression fails.
+///
+/// See [`compress`] for details.
+///
+/// Example:
+///
+/// ```rust
+/// use lzf::CompressionLevel;
+///
+/// let data = "aaaaaaaaa";
+/// let compressed = lzf::compress_with_level(data.as_bytes(), CompressionLevel::UltraFast).unwrap();
+/// ```
+#[cfg(feature = "alloc")]
+pub fn compress_with_level(data: &[u8], level: CompressionLevel) -> LzfResult<Vec<u8>> {
+    Compressor::with_level(level).compress(data)
+}
+
+/// Compress the given data, even if it does not get smaller.
+///
+/// Unlike [`compress`], this never fails for non-empty input:
+/// data that can't be compressed is stored as literal runs instead,
+/// which are decompressed with [`decompress`](crate::decompress) as usual.
+/// The output is never longer than [`max_compressed_size`](crate::max_compressed_size)
+/// of the input length.
+/// Empty input results in `InputTooShort`.
+///
+/// Example:
+///
+/// ```rust
+/// let data = "foo";
+/// let compressed = lzf::compress_always(data.as_bytes()).unwrap();
+/// assert!(compressed.len() <= lzf::max_compressed_size(data.len()));
+///
+/// let decompressed = lzf::decompress(&compressed, data.len()).unwrap();
+/// assert_eq!(data.as_bytes(), &decompressed[..]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn compress_always(data: &[u8]) -> LzfResult<Vec<u8>> {
+    Compressor::new().compress_always(data)
+}
+
+/// Compress the given data into the provided output buffer,
+/// using the provided hash table, if possible.
+/// On success the number of bytes written to `out` is returned.
+///
+/// This neither allocates nor needs a lot of stack space,
+/// so it is available without the `alloc` feature.
+/// The hash table has to have `1 << hash_log` entries,
+/// with `hash_log` in the range supported by [`CompressorConfig`].
+/// Its content does not need to be initialized, but reusing a table
+/// without clearing it might lead to different (still valid) output.
+/// See [`compress_into`](crate::compress_into) for details on the output.
+///
+/// # Panics
+///
+/// Panics if the length of `table` is not a supported power of two.
+///
+/// Example:
+///
+/// ```rust
+/// let mut table = [0; 1 << 10];
+///
+/// let data = "aaaaaaaaa";
+/// let mut out = [0; 9];
+/// let len = lzf::compress_into_with_table(data.as_bytes(), &mut out, &mut table).unwrap();
+/// let compressed = &out[..len];
+/// ```
+pub fn compress_into_with_table(
+    data: &[u8],
+    out: &mut [u8],
+    table: &mut [usize],
+) -> LzfResult<usize> {
+    let hash_log = table.len().trailing_zeros();
+    assert!(
+        table.len().is_power_of_two()
+            && (CompressorConfig::MIN_HASH_LOG..=CompressorConfig::MAX_HASH_LOG)
+                .contains(&hash_log),
+        "unsupported hash table size {}",
+        table.len()
+    );
+
+    compress_into_or_store(data, out, |out, out_buf_len| {
+        compress_with_table(data, out, out_buf_len, table, CompressionLevel::default())
+    })
+}
+
+/// Compress the given data into `out` with `compress`, like [`compress_into`] does.
+///
+/// If the data can't be compressed, but `out` is large enough,
+/// it is stored as literal runs instead.
+pub(crate) fn compress_into_or_store<F>(
+    data: &[u8],
+    out: &mut [u8],
+    compress: F,
+) -> LzfResult<usize>
+where
+    F: FnOnce(&mut SliceOutput<'_>, usize) -> LzfResult<()>,
+{
+    let out_buf_len = out.len();
+    let mut output = SliceOutput::new(out);
+    match compress(&mut output, out_buf_len) {
+        Ok(()) => Ok(output.len()),
+        Err(LzfError::NoCompressionPossible)
+            if !data.is_empty() && out_buf_len >= max_compressed_size(data.len()) =>
+        {
+            let mut output = SliceOutput::new(out);
+            store_literal_runs(data, &mut output);
+            Ok(output.len())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Store the given data as plain literal runs,
+/// taking up exactly [`max_compressed_size`] of its length.
+fn store_literal_runs<O: Output>(data: &[u8], out: &mut O) {
+    for run in data.chunks(MAX_LIT) {
+        out.push((run.len() - 1) as u8);
+        for &byte in run {
+            out.push(byte);
+        }
+    }
+}
+
+/// The trade-off between compression speed and compression ratio.
+///
+/// The faster levels mirror the `ULTRA_FAST` and `VERY_FAST` options of liblzf,
+/// which control how many hash table entries are updated after a match.
+/// Data compressed with any level is decompressed with [`decompress`](crate::decompress).
+///
+/// The default is `VeryFast`, which is also liblzf's default
+/// and what [`compress`] uses.
+#[derive(PartialEq, Eq, Clone, Debug, Copy, Default)]
+pub enum CompressionLevel {
+    /// Update a single hash table entry after a match.
+    UltraFast,
+    /// Update two hash table entries after a match.
+    #[default]
+    VeryFast,
+    /// Update the hash table for every byte of a match.
+    Normal,
+    /// Search all earlier occurrences for the longest match,
+    /// see [`compress_best`](crate::compress_best).
+    Best,
+}
+
+/// The configuration of a [`Compressor`].
+///
+/// Besides the [`CompressionLevel`], this controls the size of the hash table
+/// used to find back-references: it has `1 << hash_log` entries.
+/// A smaller table needs less memory, a larger one finds more matches
+/// in large inputs.
+/// `CompressionLevel::Best` uses it to find the earlier occurrences of the next bytes, too.
+/// Data compressed with any configuration is decompressed with [`decompress`](crate::decompress).
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// use lzf::{CompressionLevel, Compressor, CompressorConfig};
+///
+/// let config = CompressorConfig::new(12).unwrap().with_level(CompressionLevel::Normal);
+/// let mut compressor = Compressor::with_config(config);
+/// let compressed = compressor.compress("aaaaaaaaa".as_bytes()).unwrap();
+/// # }
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+pub struct CompressorConfig {
+    hash_log: u32,
+    level: CompressionLevel,
+}
+
+impl CompressorConfig {
+    /// The smallest supported `hash_log`.
+    pub const MIN_HASH_LOG: u32 = 10;
+    /// The largest supported `hash_log`.
+    pub const MAX_HASH_LOG: u32 = 22;
+
+    /// Create a configuration using a hash table of `1 << hash_log` entries
+    /// and the default compression level.
+    ///
+    /// Returns `None` if `hash_log` is not within `MIN_HASH_LOG..=MAX_HASH_LOG`.
+    pub fn new(hash_log: u32) -> Option<CompressorConfig> {
+        if !(Self::MIN_HASH_LOG..=Self::MAX_HASH_LOG).contains(&hash_log) {
+            return None;
+        }
+
+        Some(CompressorConfig {
+            hash_log,
+            level: CompressionLevel::default(),
+        })
+    }
+
+    /// Use the given compression level.
+    pub fn with_level(self, level: CompressionLevel) -> CompressorConfig {
+        CompressorConfig { level, ..self }
+    }
+
+    /// The number of hash table entries, as a power of two.
+    pub fn hash_log(&self) -> u32 {
+        self.hash_log
+    }
+
+    /// The compression level.
+    pub fn level(&self) -> CompressionLevel {
+        self.level
+    }
+}
+
+impl Default for CompressorConfig {
+    fn default() -> CompressorConfig {
+        CompressorConfig {
+            hash_log: HLOG,
+            level: CompressionLevel::default(),
+        }
+    }
+}
+
+/// A reusable LZF compressor.
+///
+/// The compressor owns the hash table used to find back-references
+/// (the `LZF_STATE` of liblzf), so compressing many small inputs
+/// does not allocate a new table for every call.
+///
+/// Entries left over from previous inputs are never trusted blindly,
+/// so reusing the table always produces valid output.
+/// That output might differ from the output of a fresh compressor though.
+/// Call [`reset`](Compressor::reset) in between to get identical results.
+///
+/// Example:
+///
+/// ```rust
+/// let mut compressor = lzf::Compressor::new();
+///
+/// for data in ["aaaaaaaaa", "bbbbbbbbb"] {
+///     let compressed = compressor.compress(data.as_bytes()).unwrap();
+///     compressor.reset();
+/// }
+/// ```
+#[cfg(feature = "alloc")]
+pub struct Compressor {
+    htab: Vec<usize>,
+    /// The chain of earlier positions with the same hash, only used by the best level.
+    prev: Vec<usize>,
+    level: CompressionLevel,
+}
+
+#[cfg(feature = "alloc")]
+impl Compressor {
+    /// Create a new compressor with an empty hash table.
+    pub fn new() -> Compressor {
+        Compressor::with_level(CompressionLevel::default())
+    }
+
+    /// Create a new compressor using the given compression level.
+    pub fn with_level(level: CompressionLevel) -> Compressor {
+        Compressor::with_config(CompressorConfig::default().with_level(level))
+    }
+
+    /// Create a new compressor using the given configuration.
+    pub fn with_config(config: CompressorConfig) -> Compressor {
+        let prev = match config.level {
+            CompressionLevel::Best => vec![0; MAX_OFF],
+            _ => Vec::new(),
+        };
+
+        Compressor {
+            htab: vec![0; 1 << config.hash_log],
+            prev,
+            level: config.level,
+        }
+    }
+
+    /// Clear the hash table without reallocating it.
+    pub fn reset(&mut self) {
+        self.htab.fill(0);
+        self.prev.fill(0);
+    }
+
+    /// Compress the given data, if possible.
+    ///
+    /// See [`compress`](crate::compress) for details.
+    pub fn compress(&mut self, data: &[u8]) -> LzfResult<Vec<u8>> {
+        // One more byte for the control byte of a literal run
+        // that might be started at the very end of the buffer.
+        let mut out = Vec::with_capacity(data.len() + 1);
+        self.compress_to(data, &mut out, data.len())?;
+
+        Ok(out)
+    }
+
+    /// Compress the given data into the provided output buffer, if possible.
+    ///
+    /// See [`compress_into`](crate::compress_into) for details.
+    pub fn compress_into(&mut self, data: &[u8], out: &mut [u8]) -> LzfResult<usize> {
+        compress_into_or_store(data, out, |out, out_buf_len| {
+            self.compress_to(data, out, out_buf_len)
+        })
+    }
+
+    /// Compress the given data and append it to `out`, if possible.
+    ///
+    /// See [`compress_to_bytes_mut`](crate::compress_to_bytes_mut) for details.
+    #[cfg(feature = "bytes")]
+    pub fn compress_to_bytes_mut(&mut self, data: &[u8], out: &mut BytesMut) -> LzfResult<usize> {
+        let start = out.len();
+        // One more byte for the control byte of a literal run
+        // that might be started at the very end of the buffer.
+        out.reserve(data.len() + 1);
+
+        let mut output = BytesMutOutput::new(out);
+        match self.compress_to(data, &mut output, data.len()) {
+            Ok(()) => Ok(output.len()),
+            Err(err) => {
+                out.truncate(start);
+                Err(err)
+            }
+        }
+    }
+
+    /// Compress the given data, even if it does not get smaller.
+    ///
+    /// See [`compress_always`](crate::compress_always) for details.
+    pub fn compress_always(&mut self, data: &[u8]) -> LzfResult<Vec<u8>> {
+        if data.is_empty() {
+            return Err(LzfError::InputTooShort);
+        }
+
+        let mut out = Vec::with_capacity(max_compressed_size(data.len()));
+        match self.compress_to(data, &mut out, data.len()) {
+            Ok(()) => {}
+            Err(LzfError::NoCompressionPossible) => {
+                out.clear();
+                store_literal_runs(data, &mut out);
+            }
+            Err(err) => return Err(err),
+        }
+
+        Ok(out)
+    }
+
+    fn compress_to<O: Output>(
+        &mut self,
+        data: &[u8],
+        out: &mut O,
+        out_buf_len: usize,
+    ) -> LzfResult<()> {
+        match self.level {
+            CompressionLevel::Best => {
+                compress_best_with(data, out, out_buf_len, &mut self.htab, &mut self.prev)
+            }
+            level => compress_with_table(data, out, out_buf_len, &mut self.htab, level),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Default for Compressor {
+    fn default() -> Compressor {
+        Compressor::new()
+    }
+}
+
+fn compress_with_table<O: Output>(
+    data: &[u8],
+    out: &mut O,
+    out_buf_len: usize,
+    htab: &mut [usize],
+    level: CompressionLevel,
+) -> LzfResult<()> {
+    let in_len = data.len();
+    let hlog = htab.len().trailing_zeros();
 
     let mut current_offset = 0;
 
-    if in_len < 2 {
+    if in_len < 2 || out_buf_len == 0 {
         return Err(LzfError::NoCompressionPossible);
     }
 
-    let mut lit: i32 = 0;
+    out.skip(); /* start run by default */
+    let mut lit = 0;
 
     let mut hval: u32;
     let mut ref_offset;
@@ -68,7 +492,7 @@ pub fn compress(data: &[u8]) -> LzfResult<Vec<u8>> {
 
     while current_offset < in_len - 2 {
         hval = next(hval, data, current_offset);
-        let hslot_idx = idx(hval);
+        let hslot_idx = idx(hval, hlog);
 
         ref_offset = htab[hslot_idx];
         htab[hslot_idx] = current_offset;
@@ -85,14 +509,17 @@ pub fn compress(data: &[u8]) -> LzfResult<Vec<u8>> {
             let mut len = 2;
             let maxlen = cmp::min(in_len - current_offset - len, MAX_REF);
 
-            /* stop run */
-            out[(out_len - lit - 1) as usize] = (lit as u8).wrapping_sub(1);
-            out_len -= not(lit); /* undo run if length is zero */
-
-            if out_len as i32 + 3 + 1 >= out_buf_len as i32 {
+            /* the run is undone below if its length is zero */
+            if out.len() - usize::from(lit == 0) + 3 + 1 >= out_buf_len {
                 return Err(LzfError::NoCompressionPossible);
             }
 
+            /* stop run */
+            out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
+            if lit == 0 {
+                out.truncate(out.len() - 1); /* undo run if length is zero */
+            }
+
             len += 1;
             while len < maxlen && data[ref_offset + len] == data[current_offset + len] {
                 len += 1;
@@ -102,16 +529,14 @@ pub fn compress(data: &[u8]) -> LzfResult<Vec<u8>> {
             current_offset += 1;
 
             if len < 7 {
-                out[out_len as usize] = (off >> 8) as u8 + (len << 5) as u8;
-                out_len += 1;
+                out.push((off >> 8) as u8 + (len << 5) as u8);
             } else {
-                out[out_len as usize] = (off >> 8) as u8 + (7 << 5);
-                out[out_len as usize + 1] = (len as u8).wrapping_sub(7);
-                out_len += 2;
+                out.push((off >> 8) as u8 + (7 << 5));
+                out.push((len as u8).wrapping_sub(7));
             }
 
-            out[out_len as usize] = off as u8;
-            out_len += 2; /* start run */
+            out.push(off as u8);
+            out.skip(); /* start run */
             lit = 0;
 
             /* we add here, because we later substract from the total length */
@@ -121,63 +546,83 @@ pub fn compress(data: &[u8]) -> LzfResult<Vec<u8>> {
                 break;
             }
 
-            hval = first(data, current_offset);
-
-            hval = next(hval, data, current_offset);
-            htab[idx(hval)] = current_offset;
-            current_offset += 1;
-
-            hval = next(hval, data, current_offset);
-            htab[idx(hval)] = current_offset;
-            current_offset += 1;
+            match level {
+                CompressionLevel::UltraFast => {
+                    current_offset += 1;
+                    hval = first(data, current_offset);
+
+                    hval = next(hval, data, current_offset);
+                    htab[idx(hval, hlog)] = current_offset;
+                    current_offset += 1;
+                }
+                CompressionLevel::Normal => {
+                    current_offset -= len - 1; /* back to the second byte of the match */
+
+                    for _ in 0..=len {
+                        hval = next(hval, data, current_offset);
+                        htab[idx(hval, hlog)] = current_offset;
+                        current_offset += 1;
+                    }
+                }
+                _ => {
+                    hval = first(data, current_offset);
+
+                    hval = next(hval, data, current_offset);
+                    htab[idx(hval, hlog)] = current_offset;
+                    current_offset += 1;
+
+                    hval = next(hval, data, current_offset);
+                    htab[idx(hval, hlog)] = current_offset;
+                    current_offset += 1;
+                }
+            }
         } else {
             /* one more literal byte we must copy */
-            if out_len >= out_buf_len as i32 {
+            if out.len() >= out_buf_len {
                 return Err(LzfError::NoCompressionPossible);
             }
 
             lit += 1;
-            out[out_len as usize] = data[current_offset];
-            out_len += 1;
+            out.push(data[current_offset]);
             current_offset += 1;
 
             if lit == MAX_LIT {
                 /* stop run */
-                out[(out_len - lit - 1) as usize] = (lit as u8).wrapping_sub(1);
+                out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
                 lit = 0;
-                out_len += 1; /* start run */
+                out.skip(); /* start run */
             }
         }
     }
 
     /* at most 3 bytes can be missing here */
-    if out_len + 3 > out_buf_len as i32 {
+    if out.len() + 3 > out_buf_len {
         return Err(LzfError::NoCompressionPossible);
     }
 
     while current_offset < in_len {
         lit += 1;
-        out[out_len as usize] = data[current_offset];
-        out_len += 1;
+        out.push(data[current_offset]);
         current_offset += 1;
 
         if lit == MAX_LIT {
             /* stop run */
-            out[(out_len - lit - 1) as usize] = (lit as u8).wrapping_sub(1);
+            out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
             lit = 0;
-            out_len += 1; /* start run */
+            out.skip(); /* start run */
         }
     }
 
     /* end run */
-    out[(out_len - lit - 1) as usize] = (lit as u8).wrapping_sub(1);
-    out_len -= not(lit); /* undo run if length is zero */
-
-    unsafe { out.set_len(out_len as usize) };
+    out.set(out.len() - lit - 1, (lit as u8).wrapping_sub(1));
+    if lit == 0 {
+        out.truncate(out.len() - 1); /* undo run if length is zero */
+    }
 
-    Ok(out)
+    Ok(())
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_compress_skips_short() {
     match compress("foo".as_bytes()) {
@@ -186,6 +631,7 @@ fn test_compress_skips_short() {
     }
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_compress_lorem() {
     let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
@@ -203,6 +649,7 @@ fn test_compress_lorem() {
     }
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_compress_decompress_lorem_round() {
     use super::decompress;
@@ -228,6 +675,7 @@ fn test_compress_decompress_lorem_round() {
     };
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_alice_wonderland_both() {
     let alice = "\r\n\r\n\r\n\r\n                ALICE'S ADVENTURES IN WONDERLAND\r\n";
@@ -245,6 +693,7 @@ fn test_alice_wonderland_both() {
     assert_eq!(&compressed[..], &c_compressed[..]);
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn quickcheck_found_bug() {
     let inp = vec![
@@ -256,9 +705,281 @@ fn quickcheck_found_bug() {
     assert_eq!(LzfError::NoCompressionPossible, compress(&inp).unwrap_err());
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn quickcheck_found_bug2() {
     let inp = vec![0];
 
     assert_eq!(LzfError::NoCompressionPossible, compress(&inp).unwrap_err());
 }
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compress_into_matches_compress() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua.";
+
+    let compressed = compress(lorem.as_bytes()).unwrap();
+
+    let mut out = [0; 512];
+    let out_len = compress_into(lorem.as_bytes(), &mut out).unwrap();
+    assert_eq!(&compressed[..], &out[..out_len]);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compress_into_short_buffer() {
+    let data = [7; 100];
+    let mut out = [0; 2];
+
+    assert_eq!(
+        LzfError::NoCompressionPossible,
+        compress_into(&data, &mut out).unwrap_err()
+    );
+    assert_eq!(
+        LzfError::NoCompressionPossible,
+        compress_into(&data, &mut []).unwrap_err()
+    );
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compress_into_short_buffer_after_literal_run() {
+    // A full literal run right before the first match.
+    let mut data: Vec<u8> = (100..129).collect();
+    data.extend(b"abcabcabcabc");
+
+    for len in 0..data.len() {
+        let mut out = vec![0; len];
+        if let Ok(out_len) = compress_into(&data, &mut out) {
+            assert_eq!(
+                &data[..],
+                &super::decompress(&out[..out_len], data.len()).unwrap()[..]
+            );
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compressor_reuse() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua.";
+    let alice = "\r\n\r\n\r\n\r\n                ALICE'S ADVENTURES IN WONDERLAND\r\n";
+
+    let long = lorem.repeat(50);
+
+    for level in [CompressionLevel::VeryFast, CompressionLevel::Best] {
+        // Entries left over from the long input point past the end of the short ones.
+        let mut compressor = Compressor::with_level(level);
+        for data in [&long, lorem, alice, lorem] {
+            let compressed = compressor.compress(data.as_bytes()).unwrap();
+            let decompressed = super::decompress(&compressed, data.len()).unwrap();
+            assert_eq!(data.as_bytes(), &decompressed[..]);
+        }
+
+        compressor.reset();
+        assert_eq!(
+            compress_with_level(alice.as_bytes(), level).unwrap(),
+            compressor.compress(alice.as_bytes()).unwrap()
+        );
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compression_levels() {
+    use super::decompress;
+
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At \
+                 vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, \
+                 no sea takimata sanctus est Lorem ipsum dolor sit amet. Lorem ipsum dolor sit \
+                 amet, consetetur sadipscing elitr, sed diam nonumy eirmod tempor invidunt ut \
+                 labore et dolore magna aliquyam erat, sed diam voluptua.";
+
+    let levels = [
+        CompressionLevel::UltraFast,
+        CompressionLevel::VeryFast,
+        CompressionLevel::Normal,
+        CompressionLevel::Best,
+    ];
+
+    let mut sizes = Vec::new();
+    for level in levels {
+        let compressed = compress_with_level(lorem.as_bytes(), level).unwrap();
+        let decompressed = decompress(&compressed, lorem.len()).unwrap();
+        assert_eq!(lorem.as_bytes(), &decompressed[..]);
+        sizes.push(compressed.len());
+    }
+
+    assert!(sizes.windows(2).all(|w| w[0] >= w[1]), "{:?}", sizes);
+    assert_eq!(
+        compress(lorem.as_bytes()).unwrap(),
+        compress_with_level(lorem.as_bytes(), CompressionLevel::default()).unwrap()
+    );
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compress_into_stores_with_max_compressed_size() {
+    use super::{compress_best_into, decompress};
+
+    // Distinct bytes, so nothing matches.
+    let noise: Vec<u8> = (0..250u32).map(|i| (i * 7919 % 251) as u8).collect();
+
+    for data in [&b"a"[..], b"foo", &noise[..32], &noise[..33], &noise] {
+        let bound = max_compressed_size(data.len());
+        let mut out = vec![0; bound];
+
+        // One byte less isn't enough.
+        assert_eq!(
+            Err(LzfError::NoCompressionPossible),
+            compress_into(data, &mut out[..bound - 1])
+        );
+
+        for len in [
+            compress_into(data, &mut out).unwrap(),
+            compress_best_into(data, &mut out).unwrap(),
+            compress_into_with_table(data, &mut out, &mut [0; 1 << 10]).unwrap(),
+        ] {
+            assert_eq!(bound, len);
+            assert_eq!(data, &decompress(&out[..len], data.len()).unwrap()[..]);
+        }
+    }
+
+    assert_eq!(
+        Err(LzfError::NoCompressionPossible),
+        compress_into(b"", &mut [0; 10])
+    );
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compress_always() {
+    use super::decompress;
+
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua.";
+    let noise: Vec<u8> = (0..1000u32).map(|i| (i * 7919 % 251) as u8).collect();
+
+    for data in [&b"a"[..], b"ab", b"foo", lorem.as_bytes(), &noise] {
+        for level in [CompressionLevel::VeryFast, CompressionLevel::Best] {
+            let compressed = Compressor::with_level(level).compress_always(data).unwrap();
+            assert!(compressed.len() <= max_compressed_size(data.len()));
+            assert_eq!(data, &decompress(&compressed, data.len()).unwrap()[..]);
+        }
+    }
+
+    assert_eq!(b"\x00a", &compress_always(b"a").unwrap()[..]);
+    assert_eq!(LzfError::InputTooShort, compress_always(b"").unwrap_err());
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compress_into_with_table() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua.";
+
+    let mut table = [0; 1 << HLOG];
+    let mut out = [0; 512];
+    let out_len = compress_into_with_table(lorem.as_bytes(), &mut out, &mut table).unwrap();
+    assert_eq!(&compress(lorem.as_bytes()).unwrap()[..], &out[..out_len]);
+}
+
+#[test]
+#[should_panic(expected = "unsupported hash table size")]
+fn test_compress_into_with_table_checks_size() {
+    let mut table = [0; 1000];
+    let _ = compress_into_with_table(b"aaaaaaaaa", &mut [0; 9], &mut table);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_compressor_config() {
+    use super::decompress;
+
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At \
+                 vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, \
+                 no sea takimata sanctus est Lorem ipsum dolor sit amet.";
+
+    assert_eq!(
+        None,
+        CompressorConfig::new(CompressorConfig::MIN_HASH_LOG - 1)
+    );
+    assert_eq!(
+        None,
+        CompressorConfig::new(CompressorConfig::MAX_HASH_LOG + 1)
+    );
+
+    for hash_log in CompressorConfig::MIN_HASH_LOG..=CompressorConfig::MAX_HASH_LOG {
+        let config = CompressorConfig::new(hash_log).unwrap();
+        let compressed = Compressor::with_config(config)
+            .compress(lorem.as_bytes())
+            .unwrap();
+        let decompressed = decompress(&compressed, lorem.len()).unwrap();
+        assert_eq!(lorem.as_bytes(), &decompressed[..]);
+    }
+
+    assert_eq!(
+        compress(lorem.as_bytes()).unwrap(),
+        Compressor::with_config(CompressorConfig::new(16).unwrap())
+            .compress(lorem.as_bytes())
+            .unwrap()
+    );
+
+    // The best level uses the table size too, but still finds the longest matches.
+    let data = lorem.repeat(100);
+    let best = super::compress_best(data.as_bytes()).unwrap();
+    for hash_log in CompressorConfig::MIN_HASH_LOG..=CompressorConfig::MAX_HASH_LOG {
+        let config = CompressorConfig::new(hash_log)
+            .unwrap()
+            .with_level(CompressionLevel::Best);
+        let compressed = Compressor::with_config(config)
+            .compress(data.as_bytes())
+            .unwrap();
+        assert_eq!(best, compressed);
+    }
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_compress_to_bytes_mut() {
+    use super::decompress_to_bytes_mut;
+
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At \
+                 vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, \
+                 no sea takimata sanctus est Lorem ipsum dolor sit amet.";
+
+    for level in [CompressionLevel::Normal, CompressionLevel::Best] {
+        let mut compressor = Compressor::with_level(level);
+        let mut out = BytesMut::from(&b"prefix"[..]);
+        let len = compressor
+            .compress_to_bytes_mut(lorem.as_bytes(), &mut out)
+            .unwrap();
+        assert_eq!(&b"prefix"[..], &out[..6]);
+        assert_eq!(compressor.compress(lorem.as_bytes()).unwrap(), &out[6..]);
+        assert_eq!(6 + len, out.len());
+
+        // Failures leave the buffer alone.
+        assert_eq!(
+            Err(LzfError::NoCompressionPossible),
+            compressor.compress_to_bytes_mut(b"foo", &mut out)
+        );
+        assert_eq!(6 + len, out.len());
+
+        let mut decompressed = BytesMut::from(&b"prefix"[..]);
+        assert_eq!(
+            Err(LzfError::OutputLimitExceeded),
+            decompress_to_bytes_mut(&out[6..], lorem.len() - 1, &mut decompressed)
+        );
+        assert_eq!(&b"prefix"[..], &decompressed[..]);
+        assert_eq!(
+            Ok(lorem.len()),
+            decompress_to_bytes_mut(&out[6..], lorem.len() + 10, &mut decompressed)
+        );
+        assert_eq!(format!("prefix{}", lorem).as_bytes(), &decompressed[..]);
+    }
+}
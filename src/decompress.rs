@@ -1,15 +1,23 @@
-use super::{LzfError, LzfResult};
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "bytes")]
+use bytes::BytesMut;
+
+use super::format::{tokens_with_limit, Token};
+use super::{Corruption, CorruptionKind, LzfError, LzfResult};
 
 /// Decompress the given data, if possible.
 /// An error will be returned if decompression fails.
 ///
 /// The length of the output buffer can be specified.
 /// If the output buffer is not large enough to hold the decompressed data,
-/// BufferTooSmall is returned.
+/// OutputLimitExceeded is returned.
 /// Otherwise the number of decompressed bytes
 /// (i.e. the original length of the data) is returned.
 ///
-/// If an error in the compressed data is detected, DataCorrupted is returned.
+/// If an error in the compressed data is detected, CorruptReference or TruncatedInput
+/// is returned, describing where (or InputTooShort if the data is empty).
 ///
 /// Example:
 ///
@@ -17,19 +25,99 @@ use super::{LzfError, LzfResult};
 /// let data = "[your-compressed-data]";
 /// let decompressed = lzf::decompress(data.as_bytes(), 10);
 /// ```
+#[cfg(feature = "alloc")]
 pub fn decompress(data: &[u8], out_len_should: usize) -> LzfResult<Vec<u8>> {
+    let mut output = vec![0; out_len_should];
+    let out_len = decompress_into(data, &mut output)?;
+
+    // Set the real length now, user might have passed a bigger buffer in the first place.
+    output.truncate(out_len);
+
+    Ok(output)
+}
+
+/// Decompress the given data and append it to `out`, producing at most `max_len` bytes.
+/// On success the number of bytes appended is returned.
+///
+/// If the decompressed data would exceed `max_len`, OutputLimitExceeded is returned.
+/// If an error in the compressed data is detected, CorruptReference or TruncatedInput
+/// is returned, describing where (or InputTooShort if the data is empty).
+/// On error `out` is left as it was.
+///
+/// The control bytes are walked first to find the decompressed length,
+/// so only as much room as the data actually needs is allocated,
+/// no matter how generous `max_len` is.
+///
+/// Example:
+///
+/// ```rust
+/// use bytes::BytesMut;
+///
+/// let compressed = lzf::compress("aaaaaaaaa".as_bytes()).unwrap();
+///
+/// let mut out = BytesMut::from(&b"header"[..]);
+/// let len = lzf::decompress_to_bytes_mut(&compressed, 9, &mut out).unwrap();
+/// assert_eq!(9, len);
+/// assert_eq!(&b"headeraaaaaaaaa"[..], &out[..]);
+/// ```
+#[cfg(feature = "bytes")]
+pub fn decompress_to_bytes_mut(
+    data: &[u8],
+    max_len: usize,
+    out: &mut BytesMut,
+) -> LzfResult<usize> {
+    let len = decompressed_len_with_limit(data, max_len)?;
+    let start = out.len();
+    out.resize(start + len, 0);
+
+    match decompress_into(data, &mut out[start..]) {
+        Ok(len) => {
+            out.truncate(start + len);
+            Ok(len)
+        }
+        Err(err) => {
+            out.truncate(start);
+            Err(err)
+        }
+    }
+}
+
+/// Decompress the given data into the provided output buffer, if possible.
+/// An error will be returned if decompression fails.
+///
+/// This mirrors `lzf_decompress` from liblzf.
+/// If `out` is not large enough to hold the decompressed data,
+/// OutputLimitExceeded is returned.
+/// Otherwise the number of decompressed bytes
+/// (i.e. the original length of the data) is returned.
+///
+/// If an error in the compressed data is detected, CorruptReference or TruncatedInput
+/// is returned, describing where (or InputTooShort if the data is empty).
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// let compressed = lzf::compress("aaaaaaaaa".as_bytes()).unwrap();
+///
+/// let mut out = [0; 9];
+/// let len = lzf::decompress_into(&compressed, &mut out).unwrap();
+/// assert_eq!(&out[..len], "aaaaaaaaa".as_bytes());
+/// # }
+/// ```
+pub fn decompress_into(data: &[u8], output: &mut [u8]) -> LzfResult<usize> {
     let mut current_offset = 0;
 
     let in_len = data.len();
     if in_len == 0 {
-        return Err(LzfError::DataCorrupted);
+        return Err(LzfError::InputTooShort);
     }
 
-    // We have sanity checks to not exceed this capacity.
-    let mut output = vec![0; out_len_should];
+    let out_len_should = output.len();
     let mut out_len: usize = 0;
 
     while current_offset < in_len {
+        let ctrl_offset = current_offset;
         let mut ctrl = data[current_offset] as usize;
         current_offset += 1;
 
@@ -37,11 +125,15 @@ pub fn decompress(data: &[u8], out_len_should: usize) -> LzfResult<Vec<u8>> {
             ctrl += 1;
 
             if out_len + ctrl > out_len_should {
-                return Err(LzfError::BufferTooSmall);
+                return Err(LzfError::OutputLimitExceeded);
             }
 
             if current_offset + ctrl > in_len {
-                return Err(LzfError::DataCorrupted);
+                return Err(corrupted(
+                    data,
+                    ctrl_offset,
+                    CorruptionKind::TruncatedLiteral,
+                ));
             }
 
             // We can simply memcpy everything from the input to the output
@@ -53,10 +145,14 @@ pub fn decompress(data: &[u8], out_len_should: usize) -> LzfResult<Vec<u8>> {
         } else {
             let mut len = ctrl >> 5;
 
-            let mut ref_offset = (((ctrl & 0x1f) << 8) + 1) as i32;
+            let mut distance = ((ctrl & 0x1f) << 8) + 1;
 
             if current_offset >= in_len {
-                return Err(LzfError::DataCorrupted);
+                return Err(corrupted(
+                    data,
+                    ctrl_offset,
+                    CorruptionKind::TruncatedBackReference,
+                ));
             }
 
             if len == 7 {
@@ -64,48 +160,184 @@ pub fn decompress(data: &[u8], out_len_should: usize) -> LzfResult<Vec<u8>> {
                 current_offset += 1;
 
                 if current_offset >= in_len {
-                    return Err(LzfError::DataCorrupted);
+                    return Err(corrupted(
+                        data,
+                        ctrl_offset,
+                        CorruptionKind::TruncatedBackReference,
+                    ));
                 }
             }
 
-            ref_offset += data[current_offset] as i32;
+            distance += data[current_offset] as usize;
             current_offset += 1;
 
             if out_len + len + 2 > out_len_should {
-                return Err(LzfError::BufferTooSmall);
+                return Err(LzfError::OutputLimitExceeded);
             }
 
-            let mut ref_pos = (out_len as i32) - ref_offset;
-            if ref_pos < 0 {
-                return Err(LzfError::DataCorrupted);
+            if distance > out_len {
+                return Err(corrupted(
+                    data,
+                    ctrl_offset,
+                    CorruptionKind::ReferenceBeforeStart,
+                ));
             }
 
-            let c = output[ref_pos as usize];
-            output[out_len] = c;
-            out_len += 1;
-            ref_pos += 1;
-
-            let c = output[ref_pos as usize];
-            output[out_len] = c;
-            out_len += 1;
-            ref_pos += 1;
-
-            while len > 0 {
-                let c = output[ref_pos as usize];
-                output[out_len] = c;
-                out_len += 1;
-                ref_pos += 1;
-                len -= 1;
-            }
+            copy_back_reference(output, out_len, distance, len + 2);
+            out_len += len + 2;
         }
     }
 
-    // Set the real length now, user might have passed a bigger buffer in the first place.
-    unsafe { output.set_len(out_len) };
+    Ok(out_len)
+}
+
+/// Decompress the given data without knowing its original length.
+///
+/// The output grows as needed while decoding.
+/// If an error in the compressed data is detected, CorruptReference or TruncatedInput
+/// is returned, describing where (or InputTooShort if the data is empty).
+///
+/// This trusts the input to not expand into an unreasonable amount of memory.
+/// Use [`decompress_to_vec_with_limit`] for untrusted input.
+///
+/// Example:
+///
+/// ```rust
+/// let compressed = lzf::compress("aaaaaaaaa".as_bytes()).unwrap();
+///
+/// let decompressed = lzf::decompress_to_vec(&compressed).unwrap();
+/// assert_eq!(&decompressed[..], "aaaaaaaaa".as_bytes());
+/// ```
+#[cfg(feature = "alloc")]
+pub fn decompress_to_vec(data: &[u8]) -> LzfResult<Vec<u8>> {
+    decompress_to_vec_with_limit(data, usize::MAX)
+}
+
+/// Decompress the given data without knowing its original length,
+/// producing at most `max_len` bytes.
+///
+/// If the decompressed data would exceed `max_len`, OutputLimitExceeded is returned.
+/// If an error in the compressed data is detected, CorruptReference or TruncatedInput
+/// is returned, describing where (or InputTooShort if the data is empty).
+#[cfg(feature = "alloc")]
+pub fn decompress_to_vec_with_limit(data: &[u8], max_len: usize) -> LzfResult<Vec<u8>> {
+    if data.is_empty() {
+        return Err(LzfError::InputTooShort);
+    }
+
+    let mut output = Vec::with_capacity(data.len().saturating_mul(2).min(max_len));
+
+    for token in tokens_with_limit(data, max_len) {
+        match token? {
+            Token::Literal { offset, len } => {
+                output.extend_from_slice(&data[offset..offset + len]);
+            }
+            Token::BackRef { distance, len } => {
+                let out_len = output.len();
+                output.resize(out_len + len, 0);
+                copy_back_reference(&mut output, out_len, distance, len);
+            }
+        }
+    }
 
     Ok(output)
 }
 
+/// Compute the length of the decompressed data without decompressing it.
+///
+/// This walks the control bytes of the compressed data only.
+/// If an error in the compressed data is detected, CorruptReference or TruncatedInput
+/// is returned, describing where (or InputTooShort if the data is empty).
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// let compressed = lzf::compress("aaaaaaaaa".as_bytes()).unwrap();
+/// assert_eq!(9, lzf::decompressed_len(&compressed).unwrap());
+/// # }
+/// ```
+pub fn decompressed_len(data: &[u8]) -> LzfResult<usize> {
+    decompressed_len_with_limit(data, usize::MAX)
+}
+
+/// Like [`decompressed_len`], but returns OutputLimitExceeded
+/// as soon as the length exceeds `max_len`.
+fn decompressed_len_with_limit(data: &[u8], max_len: usize) -> LzfResult<usize> {
+    if data.is_empty() {
+        return Err(LzfError::InputTooShort);
+    }
+
+    tokens_with_limit(data, max_len).try_fold(0, |out_len, token| match token? {
+        Token::Literal { len, .. } | Token::BackRef { len, .. } => Ok(out_len + len),
+    })
+}
+
+/// Check that the given data decompresses to exactly `expected_len` bytes,
+/// without decompressing it.
+///
+/// Like [`decompressed_len`], this only walks the control bytes,
+/// so it is a lot cheaper than decompressing the data.
+/// The first problem found is returned:
+/// CorruptReference or TruncatedInput at the offset of the offending control byte,
+/// OutputLimitExceeded if the data decompresses to more than `expected_len` bytes,
+/// or InputTooShort if it decompresses to fewer bytes (or is empty).
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// use lzf::LzfError;
+///
+/// let compressed = lzf::compress("aaaaaaaaa".as_bytes()).unwrap();
+/// assert_eq!(Ok(()), lzf::verify(&compressed, 9));
+/// assert_eq!(Err(LzfError::OutputLimitExceeded), lzf::verify(&compressed, 8));
+/// assert_eq!(Err(LzfError::InputTooShort), lzf::verify(&compressed, 10));
+/// # }
+/// ```
+pub fn verify(data: &[u8], expected_len: usize) -> LzfResult<()> {
+    if decompressed_len_with_limit(data, expected_len)? < expected_len {
+        return Err(LzfError::InputTooShort);
+    }
+
+    Ok(())
+}
+
+/// Copy `len` bytes starting `distance` bytes before `out_len` to `out_len`.
+///
+/// If the reference overlaps with the bytes being written (`distance < len`),
+/// the last `distance` bytes are repeated.
+fn copy_back_reference(output: &mut [u8], out_len: usize, distance: usize, len: usize) {
+    let ref_pos = out_len - distance;
+
+    if distance >= len {
+        output.copy_within(ref_pos..ref_pos + len, out_len);
+    } else if distance == 1 {
+        let c = output[ref_pos];
+        output[out_len..out_len + len].fill(c);
+    } else {
+        // Each copy doubles the repeated pattern in front of the write position,
+        // so the chunks grow until the whole reference is written.
+        let mut copied = 0;
+        while copied < len {
+            let chunk = (len - copied).min(distance + copied);
+            output.copy_within(ref_pos..ref_pos + chunk, out_len + copied);
+            copied += chunk;
+        }
+    }
+}
+
+pub(crate) fn corrupted(data: &[u8], ctrl_offset: usize, kind: CorruptionKind) -> LzfError {
+    let corruption = Corruption::new(ctrl_offset, data[ctrl_offset], kind);
+    match kind {
+        CorruptionKind::ReferenceBeforeStart => LzfError::CorruptReference(corruption),
+        CorruptionKind::TruncatedLiteral | CorruptionKind::TruncatedBackReference => {
+            LzfError::TruncatedInput(corruption)
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
 #[test]
 fn test_decompress_lorem() {
     use super::compress;
@@ -126,6 +358,7 @@ fn test_decompress_lorem() {
     assert_eq!(lorem.len(), decompressed.len());
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_decompress_fails_with_short_buffer() {
     use super::compress;
@@ -144,20 +377,29 @@ fn test_decompress_fails_with_short_buffer() {
 
     match decompress(&compressed, 10) {
         Ok(_) => panic!("Decompression worked. That should not happen"),
-        Err(err) => assert_eq!(LzfError::BufferTooSmall, err),
+        Err(err) => assert_eq!(LzfError::OutputLimitExceeded, err),
     }
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_decompress_fails_for_corrupted_data() {
     let lorem = "Lorem ipsum dolor sit amet";
 
     match decompress(lorem.as_bytes(), lorem.len()) {
         Ok(_) => panic!("Decompression worked. That should not happen"),
-        Err(err) => assert_eq!(LzfError::DataCorrupted, err),
+        Err(err) => assert_eq!(
+            LzfError::CorruptReference(Corruption::new(
+                0,
+                b'L',
+                CorruptionKind::ReferenceBeforeStart
+            )),
+            err
+        ),
     }
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_alice_wonderland() {
     use super::compress;
@@ -178,6 +420,7 @@ fn test_alice_wonderland() {
     }
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn easily_compressible() {
     // RDB regression
@@ -190,7 +433,170 @@ fn easily_compressible() {
     assert_eq!(97, text[199]);
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_empty() {
-    assert_eq!(LzfError::DataCorrupted, decompress(&[], 10).unwrap_err());
+    assert_eq!(LzfError::InputTooShort, decompress(&[], 10).unwrap_err());
+}
+
+#[test]
+fn test_decompress_into_reuses_buffer() {
+    let data = vec![1, 97, 97, 224, 187, 0, 1, 97, 97];
+    let mut out = [0; 300];
+
+    assert_eq!(200, decompress_into(&data, &mut out).unwrap());
+    assert!(out[..200].iter().all(|&c| c == 97));
+
+    out = [0; 300];
+    assert_eq!(
+        LzfError::OutputLimitExceeded,
+        decompress_into(&data, &mut out[..100]).unwrap_err()
+    );
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_decompress_to_vec() {
+    use super::compress;
+
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At \
+                 vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, \
+                 no sea takimata sanctus est Lorem ipsum dolor sit amet.";
+
+    let compressed = compress(lorem.as_bytes()).unwrap();
+    assert_eq!(
+        lorem.as_bytes(),
+        &decompress_to_vec(&compressed).unwrap()[..]
+    );
+    assert_eq!(lorem.len(), decompressed_len(&compressed).unwrap());
+
+    let data = vec![1, 97, 97, 224, 187, 0, 1, 97, 97];
+    assert_eq!(vec![97; 200], decompress_to_vec(&data).unwrap());
+    assert_eq!(200, decompressed_len(&data).unwrap());
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_decompress_to_vec_with_limit() {
+    let data = vec![1, 97, 97, 224, 187, 0, 1, 97, 97];
+
+    assert_eq!(
+        vec![97; 200],
+        decompress_to_vec_with_limit(&data, 200).unwrap()
+    );
+    assert_eq!(
+        LzfError::OutputLimitExceeded,
+        decompress_to_vec_with_limit(&data, 199).unwrap_err()
+    );
+    assert_eq!(
+        LzfError::OutputLimitExceeded,
+        decompress_to_vec_with_limit(&data, 0).unwrap_err()
+    );
+
+    // Truncated and too long: the limit is checked first, like liblzf does.
+    for case in [&b"\x02aa"[..], b"\x01aa\x20\x05"] {
+        let err = decompress_into(case, &mut [0; 2]).unwrap_err();
+        assert_eq!(LzfError::OutputLimitExceeded, err);
+        assert_eq!(err, decompress_to_vec_with_limit(case, 2).unwrap_err());
+        assert_eq!(err, verify(case, 2).unwrap_err());
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_decompress_to_vec_corrupted() {
+    use CorruptionKind::*;
+
+    let cases: &[(&[u8], usize, u8, CorruptionKind)] = &[
+        (b"Lorem ipsum dolor sit amet", 0, b'L', ReferenceBeforeStart),
+        (b"\x02aa", 0, 0x02, TruncatedLiteral),
+        (b"\x01aa\xe0", 3, 0xe0, TruncatedBackReference),
+        (b"\x01aa\xe0\xbb", 3, 0xe0, TruncatedBackReference),
+        (b"\x01aa\x20\x02", 3, 0x20, ReferenceBeforeStart),
+    ];
+
+    for &(case, offset, control, kind) in cases {
+        let err = corrupted(case, offset, kind);
+        assert_eq!(control, case[offset]);
+        assert_eq!(err, decompress_to_vec(case).unwrap_err());
+        assert_eq!(err, decompressed_len(case).unwrap_err());
+        assert_eq!(err, decompress_into(case, &mut [0; 100]).unwrap_err());
+    }
+
+    assert_eq!(LzfError::InputTooShort, decompress_to_vec(b"").unwrap_err());
+    assert_eq!(LzfError::InputTooShort, decompressed_len(b"").unwrap_err());
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_overlapping_back_references() {
+    // A literal run of `distance` bytes, followed by a reference repeating it.
+    for distance in 1..=8 {
+        for len in 3..=264 {
+            let mut data = vec![distance as u8 - 1];
+            data.extend(1..=distance as u8);
+            if len - 2 < 7 {
+                data.push(((len - 2) << 5) as u8);
+            } else {
+                data.push(7 << 5);
+                data.push((len - 2 - 7) as u8);
+            }
+            data.push(distance as u8 - 1);
+
+            let expected: Vec<u8> = (0..distance + len)
+                .map(|i| (i % distance) as u8 + 1)
+                .collect();
+            assert_eq!(expected, decompress(&data, distance + len).unwrap());
+            assert_eq!(expected, decompress_to_vec(&data).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_verify() {
+    let data = [1, 97, 97, 224, 187, 0, 1, 97, 97];
+
+    assert_eq!(Ok(()), verify(&data, 200));
+    assert_eq!(Err(LzfError::OutputLimitExceeded), verify(&data, 199));
+    assert_eq!(Err(LzfError::OutputLimitExceeded), verify(&data, 0));
+    assert_eq!(Err(LzfError::InputTooShort), verify(&data, 201));
+    assert_eq!(Err(LzfError::InputTooShort), verify(&[], 0));
+    assert_eq!(
+        Err(corrupted(
+            &data[..4],
+            3,
+            CorruptionKind::TruncatedBackReference
+        )),
+        verify(&data[..4], 200)
+    );
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_decompress_to_bytes_mut_generous_limit() {
+    let data = [1, 97, 97, 224, 187, 0, 1, 97, 97];
+
+    // Only the room actually needed is allocated, not the whole limit.
+    let mut out = BytesMut::new();
+    assert_eq!(
+        Ok(200),
+        decompress_to_bytes_mut(&data, usize::MAX, &mut out)
+    );
+    assert_eq!(vec![97; 200], out);
+    assert!(out.capacity() < 1024);
+
+    assert_eq!(
+        Err(LzfError::OutputLimitExceeded),
+        decompress_to_bytes_mut(&data, 199, &mut out)
+    );
+    assert_eq!(
+        Err(corrupted(
+            &data[..4],
+            3,
+            CorruptionKind::TruncatedBackReference
+        )),
+        decompress_to_bytes_mut(&data[..4], usize::MAX, &mut out)
+    );
+    assert_eq!(200, out.len());
 }
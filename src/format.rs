@@ -0,0 +1,295 @@
+//! Constants and limits of the LZF format.
+//!
+//! Compressed data is a sequence of literal runs and back-references,
+//! each starting with a control byte:
+//!
+//! * `000LLLLL`: a literal run of `L + 1` bytes, which follow the control byte.
+//! * `LLLooooo oooooooo`: a back-reference of `L + 2` bytes,
+//!   starting `o + 1` bytes before the end of the output.
+//! * `111ooooo LLLLLLLL oooooooo`: a back-reference of `L + 9` bytes,
+//!   starting `o + 1` bytes before the end of the output.
+//!
+//! A back-reference may overlap with the bytes it produces, repeating the last `o + 1` bytes.
+//! The names of the constants follow `lzfP.h` of liblzf.
+
+use core::iter::FusedIterator;
+
+use super::decompress::corrupted;
+use super::{CorruptionKind, LzfError, LzfResult};
+
+/// The longest literal run, in bytes.
+pub const MAX_LIT: usize = 1 << 5;
+
+/// The largest distance of a back-reference, in bytes.
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// use lzf::format::MAX_OFF;
+///
+/// // MAX_OFF bytes of literal runs, then a reference of 3 bytes to the very first byte.
+/// let mut data = Vec::new();
+/// for _ in 0..MAX_OFF / 32 {
+///     data.push(31);
+///     data.extend_from_slice(&[7; 32]);
+/// }
+/// data.extend_from_slice(&[0x3f, 0xff]);
+///
+/// let decompressed = lzf::decompress_to_vec(&data).unwrap();
+/// assert_eq!(MAX_OFF + 3, decompressed.len());
+/// # }
+/// ```
+pub const MAX_OFF: usize = 1 << 13;
+
+/// The longest back-reference, in bytes.
+pub const MAX_REF: usize = (1 << 8) + (1 << 3);
+
+/// The maximum length of compressed data for input of the given length.
+///
+/// Incompressible data is stored as literal runs of up to [`MAX_LIT`] bytes,
+/// each preceded by a control byte.
+/// The output of [`compress_always`](crate::compress_always) never exceeds this bound.
+/// [`compress_into`](crate::compress_into) and [`compress_best_into`](crate::compress_best_into)
+/// store incompressible data the same way if the output buffer is at least this large,
+/// so such a buffer is always large enough for non-empty input.
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// use lzf::format::max_compressed_size;
+///
+/// assert_eq!(0, max_compressed_size(0));
+/// assert_eq!(2, max_compressed_size(1));
+/// assert_eq!(33, max_compressed_size(32));
+/// assert_eq!(35, max_compressed_size(33));
+///
+/// let noise: Vec<u8> = (0..1000u32).map(|i| (i * 7919 % 251) as u8).collect();
+/// for data in [&b"aaaaaaaaa"[..], b"foo", &noise] {
+///     let bound = max_compressed_size(data.len());
+///     assert!(lzf::compress_always(data).unwrap().len() <= bound);
+///
+///     let mut out = vec![0; bound];
+///     let len = lzf::compress_into(data, &mut out).unwrap();
+///     assert_eq!(data, &lzf::decompress(&out[..len], data.len()).unwrap()[..]);
+/// }
+/// # }
+/// ```
+pub const fn max_compressed_size(len: usize) -> usize {
+    len + len.div_ceil(MAX_LIT)
+}
+
+/// The maximum length compressed data of the given length can decompress to.
+///
+/// The longest back-reference produces [`MAX_REF`] bytes from three bytes of input,
+/// so every 3 bytes of input produce at most `MAX_REF` (264) bytes of output,
+/// i.e. at most 88 bytes of output per byte of input.
+/// Lengths read from untrusted headers are checked against this
+/// before allocating the output.
+#[cfg(feature = "alloc")]
+pub(crate) const fn max_decompressed_size(len: usize) -> usize {
+    len.saturating_mul(MAX_REF.div_ceil(3))
+}
+
+/// A literal run or back-reference in compressed data.
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+pub enum Token {
+    /// `len` bytes copied from the compressed data, starting at `offset`.
+    Literal {
+        /// The offset of the first byte of the run in the compressed data.
+        offset: usize,
+        /// The number of bytes in the run.
+        len: usize,
+    },
+    /// `len` bytes copied from the output, starting `distance` bytes before its end.
+    BackRef {
+        /// How far back the copied bytes start.
+        distance: usize,
+        /// The number of bytes copied.
+        len: usize,
+    },
+}
+
+/// Parse compressed data into its literal runs and back-references.
+///
+/// This interprets the control bytes just like [`decompress`](crate::decompress)
+/// without producing any output, which is useful to inspect corrupted data
+/// or to gather statistics.
+/// If the data is corrupted, the corruption is reported as the last item.
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// use lzf::format::{tokens, Token};
+///
+/// let compressed = lzf::compress(&[97; 200]).unwrap();
+///
+/// let mut literal_len = 0;
+/// let mut matched_len = 0;
+/// for token in tokens(&compressed) {
+///     match token.unwrap() {
+///         Token::Literal { len, .. } => literal_len += len,
+///         Token::BackRef { len, .. } => matched_len += len,
+///     }
+/// }
+/// assert_eq!(200, literal_len + matched_len);
+/// assert!(matched_len > literal_len);
+/// # }
+/// ```
+pub fn tokens(data: &[u8]) -> Tokens<'_> {
+    tokens_with_limit(data, usize::MAX)
+}
+
+/// Like [`tokens`], but fails with OutputLimitExceeded
+/// once the output would grow beyond `max_len` bytes.
+///
+/// The limit is checked at the same point as in [`decompress_into`](crate::decompress_into),
+/// so both report the same error for data that is both corrupted and too long.
+pub(crate) fn tokens_with_limit(data: &[u8], max_len: usize) -> Tokens<'_> {
+    Tokens {
+        data,
+        pos: 0,
+        out_len: 0,
+        max_len,
+    }
+}
+
+/// An iterator over the tokens of compressed data, created by [`tokens`].
+#[derive(Clone, Debug)]
+pub struct Tokens<'a> {
+    data: &'a [u8],
+    pos: usize,
+    out_len: usize,
+    max_len: usize,
+}
+
+impl Tokens<'_> {
+    fn parse(&mut self) -> LzfResult<Token> {
+        let data = self.data;
+        let ctrl_offset = self.pos;
+        let ctrl = data[ctrl_offset] as usize;
+        let mut current_offset = ctrl_offset + 1;
+
+        let token = if ctrl < (1 << 5) {
+            let len = ctrl + 1;
+
+            if len > self.max_len - self.out_len {
+                return Err(LzfError::OutputLimitExceeded);
+            }
+
+            if current_offset + len > data.len() {
+                return Err(corrupted(
+                    data,
+                    ctrl_offset,
+                    CorruptionKind::TruncatedLiteral,
+                ));
+            }
+
+            let offset = current_offset;
+            current_offset += len;
+            Token::Literal { offset, len }
+        } else {
+            let truncated = || corrupted(data, ctrl_offset, CorruptionKind::TruncatedBackReference);
+            let mut len = ctrl >> 5;
+
+            if len == 7 {
+                len += *data.get(current_offset).ok_or_else(truncated)? as usize;
+                current_offset += 1;
+            }
+            len += 2;
+
+            let distance = (((ctrl & 0x1f) << 8)
+                | *data.get(current_offset).ok_or_else(truncated)? as usize)
+                + 1;
+            current_offset += 1;
+
+            if len > self.max_len - self.out_len {
+                return Err(LzfError::OutputLimitExceeded);
+            }
+
+            if distance > self.out_len {
+                return Err(corrupted(
+                    data,
+                    ctrl_offset,
+                    CorruptionKind::ReferenceBeforeStart,
+                ));
+            }
+
+            Token::BackRef { distance, len }
+        };
+
+        self.pos = current_offset;
+        Ok(token)
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = LzfResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        let token = self.parse();
+        match token {
+            Ok(Token::Literal { len, .. }) | Ok(Token::BackRef { len, .. }) => self.out_len += len,
+            // Stop after the first error.
+            Err(_) => self.pos = self.data.len(),
+        }
+
+        Some(token)
+    }
+}
+
+impl FusedIterator for Tokens<'_> {}
+
+#[test]
+fn test_tokens() {
+    // The data of the `easily_compressible` regression test.
+    let data = [1, 97, 97, 224, 187, 0, 1, 97, 97];
+
+    let tokens: Vec<_> = tokens(&data).map(Result::unwrap).collect();
+    assert_eq!(
+        vec![
+            Token::Literal { offset: 1, len: 2 },
+            Token::BackRef {
+                distance: 1,
+                len: 196
+            },
+            Token::Literal { offset: 7, len: 2 },
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn test_tokens_corrupted() {
+    let mut iter = tokens(b"\x01aa\x20\x02");
+    assert_eq!(Some(Ok(Token::Literal { offset: 1, len: 2 })), iter.next());
+    assert_eq!(
+        Some(Err(corrupted(
+            b"\x01aa\x20\x02",
+            3,
+            CorruptionKind::ReferenceBeforeStart
+        ))),
+        iter.next()
+    );
+    assert_eq!(None, iter.next());
+
+    let mut iter = tokens(b"\x01aa\xe0");
+    iter.next();
+    assert_eq!(
+        Some(Err(corrupted(
+            b"\x01aa\xe0",
+            3,
+            CorruptionKind::TruncatedBackReference
+        ))),
+        iter.next()
+    );
+    assert_eq!(None, iter.next());
+
+    assert_eq!(None, tokens(&[]).next());
+}
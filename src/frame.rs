@@ -0,0 +1,7 @@
+//! The `ZV` block header shared by the `lzf` utility and compress-lzf.
+
+pub(crate) const MAGIC: [u8; 2] = *b"ZV";
+pub(crate) const TYPE_UNCOMPRESSED: u8 = 0;
+pub(crate) const TYPE_COMPRESSED: u8 = 1;
+pub(crate) const TYPE0_HDR_SIZE: usize = 5;
+pub(crate) const TYPE1_HDR_SIZE: usize = 7;
@@ -9,39 +9,149 @@
 //! # Basic Operation
 //!
 //! ```rust
+//! # #[cfg(feature = "alloc")] {
 //! # use lzf;
 //! let data = "aaaaaaaaa";
 //!
 //! let compressed = lzf::compress(data.as_bytes()).unwrap();
 //!
 //! let decompressed = lzf::decompress(&compressed, data.len()).unwrap();
+//! # }
 //! ```
 #![deny(missing_docs)]
+#![forbid(unsafe_code)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use std::fmt;
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
+use core::fmt;
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub mod async_stream;
+#[cfg(feature = "alloc")]
+mod best;
+#[cfg(feature = "tokio-util")]
+pub mod codec;
 mod compress;
 mod decompress;
-pub use compress::compress;
-pub use decompress::decompress;
+pub mod format;
+#[cfg(feature = "alloc")]
+mod frame;
+#[cfg(feature = "alloc")]
+pub mod ning;
+mod output;
+#[cfg(feature = "alloc")]
+pub mod redis;
+#[cfg(feature = "alloc")]
+mod sized;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "alloc")]
+pub use best::{compress_best, compress_best_into};
+#[cfg(feature = "bytes")]
+pub use compress::compress_to_bytes_mut;
+#[cfg(feature = "alloc")]
+pub use compress::{compress, compress_always, compress_into, compress_with_level, Compressor};
+pub use compress::{compress_into_with_table, CompressionLevel, CompressorConfig};
+#[cfg(feature = "bytes")]
+pub use decompress::decompress_to_bytes_mut;
+#[cfg(feature = "alloc")]
+pub use decompress::{decompress, decompress_to_vec, decompress_to_vec_with_limit};
+pub use decompress::{decompress_into, decompressed_len, verify};
+pub use format::max_compressed_size;
+#[cfg(feature = "alloc")]
+pub use sized::{compress_with_size, decompress_with_size};
 
 /// Errors that can occur during Compression or Decompression.
+///
+/// New variants may be added in the future, so matches need a wildcard arm.
+///
+/// `BufferTooSmall` and `DataCorrupted` are no longer returned, so matching on them
+/// silently stops working. Use [`is_output_limit`](LzfError::is_output_limit)
+/// and [`is_corrupted`](LzfError::is_corrupted) instead, which also cover their replacements.
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// let mut out = vec![0; 4];
+/// let compressed = lzf::compress(&[7; 100]).unwrap();
+///
+/// match lzf::decompress_into(&compressed, &mut out) {
+///     Err(err) if err.is_output_limit() => out.resize(100, 0),
+///     _ => unreachable!(),
+/// }
+/// assert_eq!(Ok(100), lzf::decompress_into(&compressed, &mut out));
+/// # }
+/// ```
+#[non_exhaustive]
 #[derive(PartialEq, Eq, Clone, Debug, Copy)]
 pub enum LzfError {
     /// The provided buffer is too small to handle the uncompressed data
+    #[deprecated(
+        note = "no longer returned, use `LzfError::is_output_limit` or `OutputLimitExceeded`"
+    )]
     BufferTooSmall,
     /// The given compressed data is corrupted
+    #[deprecated(
+        note = "no longer returned, use `LzfError::is_corrupted` or `InputTooShort`, `CorruptReference`, `TruncatedInput` and `InvalidFrame`"
+    )]
     DataCorrupted,
     /// The given data can't be compressed
     NoCompressionPossible,
     /// An unknown error occured
+    #[deprecated(note = "no longer returned")]
     UnknownError(i32),
+    /// The output would exceed the provided buffer or length limit
+    OutputLimitExceeded,
+    /// The given compressed data is too short to be decompressed
+    InputTooShort,
+    /// A back-reference in the compressed data points before the start of the output
+    CorruptReference(Corruption),
+    /// The compressed data ends in the middle of a literal run or back-reference
+    TruncatedInput(Corruption),
+    /// The framing around the compressed data (e.g. a header or length) is invalid
+    InvalidFrame,
+}
+
+impl LzfError {
+    /// Whether the compressed data or its framing is invalid.
+    ///
+    /// This is true for `InputTooShort`, `CorruptReference`, `TruncatedInput`, `InvalidFrame`
+    /// and the deprecated `DataCorrupted`, the error all of them used to be reported as.
+    pub fn is_corrupted(&self) -> bool {
+        #[allow(deprecated)]
+        let corrupted = matches!(
+            self,
+            LzfError::DataCorrupted
+                | LzfError::InputTooShort
+                | LzfError::CorruptReference(_)
+                | LzfError::TruncatedInput(_)
+                | LzfError::InvalidFrame
+        );
+        corrupted
+    }
+
+    /// Whether the output would not fit into the provided buffer or length limit.
+    ///
+    /// This is true for `OutputLimitExceeded` and the deprecated `BufferTooSmall`,
+    /// the error it used to be reported as.
+    pub fn is_output_limit(&self) -> bool {
+        #[allow(deprecated)]
+        let output_limit = matches!(
+            self,
+            LzfError::BufferTooSmall | LzfError::OutputLimitExceeded
+        );
+        output_limit
+    }
 }
 
 impl fmt::Display for LzfError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[allow(deprecated)]
         match *self {
-            LzfError::BufferTooSmall => {
+            LzfError::BufferTooSmall | LzfError::OutputLimitExceeded => {
                 write!(
                     f,
                     "the given buffer is too small to handle the uncompressed data"
@@ -56,6 +166,102 @@ impl fmt::Display for LzfError {
             LzfError::UnknownError(err) => {
                 write!(f, "unknown error, code {}", err)
             }
+            LzfError::InputTooShort => {
+                write!(f, "the given data is too short")
+            }
+            LzfError::CorruptReference(corruption) | LzfError::TruncatedInput(corruption) => {
+                write!(f, "the given data is corrupted: {}", corruption)
+            }
+            LzfError::InvalidFrame => {
+                write!(f, "the given data is not framed correctly")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LzfError {}
+
+/// Where and how decompression found the compressed data to be corrupted.
+///
+/// Example:
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// use lzf::{CorruptionKind, LzfError};
+///
+/// // A literal run of 4 bytes, but only 3 follow.
+/// match lzf::decompress(&[3, 1, 2, 3], 10) {
+///     Err(LzfError::TruncatedInput(corruption)) => {
+///         assert_eq!(0, corruption.offset());
+///         assert_eq!(3, corruption.control());
+///         assert_eq!(CorruptionKind::TruncatedLiteral, corruption.kind());
+///     }
+///     _ => unreachable!(),
+/// }
+/// # }
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+pub struct Corruption {
+    offset: usize,
+    control: u8,
+    kind: CorruptionKind,
+}
+
+impl Corruption {
+    pub(crate) fn new(offset: usize, control: u8, kind: CorruptionKind) -> Self {
+        Corruption {
+            offset,
+            control,
+            kind,
+        }
+    }
+
+    /// The offset of the failing control byte in the compressed data.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The failing control byte.
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    /// What is wrong with the data following the control byte.
+    pub fn kind(&self) -> CorruptionKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at offset {} (control byte {:#04x})",
+            self.kind, self.offset, self.control
+        )
+    }
+}
+
+/// The kind of corruption found during decompression.
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+pub enum CorruptionKind {
+    /// The input ends within a literal run
+    TruncatedLiteral,
+    /// The input ends within a back-reference
+    TruncatedBackReference,
+    /// A back-reference points before the start of the output
+    ReferenceBeforeStart,
+}
+
+impl fmt::Display for CorruptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CorruptionKind::TruncatedLiteral => write!(f, "truncated literal"),
+            CorruptionKind::TruncatedBackReference => write!(f, "truncated back-reference"),
+            CorruptionKind::ReferenceBeforeStart => {
+                write!(f, "back-reference before the start of the output")
+            }
         }
     }
 }
@@ -63,6 +269,7 @@ impl fmt::Display for LzfError {
 /// A Result providing the underlying data or a compression/decompression error
 pub type LzfResult<T> = Result<T, LzfError>;
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_compress_skips_short() {
     match compress("foo".as_bytes()) {
@@ -71,6 +278,7 @@ fn test_compress_skips_short() {
     }
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_compress_lorem() {
     let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
@@ -88,6 +296,7 @@ fn test_compress_lorem() {
     }
 }
 
+#[cfg(feature = "alloc")]
 #[test]
 fn test_compress_decompress_lorem_round() {
     let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
@@ -111,7 +320,50 @@ fn test_compress_decompress_lorem_round() {
     };
 }
 
-#[cfg(test)]
+#[cfg(feature = "std")]
+#[test]
+fn test_error_source() {
+    fn decompress_boxed(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(decompress(data, 10)?)
+    }
+
+    let err = decompress_boxed(&[0x20, 0x00]).unwrap_err();
+    assert_eq!(
+        "the given data is corrupted: back-reference before the start of the output \
+         at offset 0 (control byte 0x20)",
+        err.to_string()
+    );
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_error_classification() {
+    let corruption = Corruption::new(0, 0x20, CorruptionKind::ReferenceBeforeStart);
+    #[allow(deprecated)]
+    let corrupted = [
+        LzfError::DataCorrupted,
+        LzfError::InputTooShort,
+        LzfError::CorruptReference(corruption),
+        LzfError::TruncatedInput(corruption),
+        LzfError::InvalidFrame,
+    ];
+    for err in corrupted {
+        assert!(err.is_corrupted() && !err.is_output_limit(), "{:?}", err);
+    }
+
+    #[allow(deprecated)]
+    let output_limit = [LzfError::BufferTooSmall, LzfError::OutputLimitExceeded];
+    for err in output_limit {
+        assert!(err.is_output_limit() && !err.is_corrupted(), "{:?}", err);
+    }
+
+    assert!(!LzfError::NoCompressionPossible.is_corrupted());
+    assert!(!LzfError::NoCompressionPossible.is_output_limit());
+    assert!(decompress(&[0x20, 0x00], 10).unwrap_err().is_corrupted());
+    assert!(decompress(&[0x00, 0x61], 0).unwrap_err().is_output_limit());
+}
+
+#[cfg(all(test, feature = "alloc"))]
 mod quickcheck_test {
     use super::*;
     use quickcheck::{quickcheck, TestResult};
@@ -120,8 +372,7 @@ mod quickcheck_test {
         let compr = match compress(&data) {
             Ok(compr) => compr,
             Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
-            Err(LzfError::DataCorrupted) => return TestResult::discard(),
-            e @ _ => panic!("{:?}", e),
+            e => panic!("{:?}", e),
         };
         let decompr = decompress(&compr, data.len()).unwrap();
         TestResult::from_bool(data == decompr)
@@ -131,4 +382,122 @@ mod quickcheck_test {
     fn qc_roundtrip() {
         quickcheck(compress_decompress_round as fn(_) -> _);
     }
+
+    fn compressor_reuse_round(first: Vec<u8>, second: Vec<u8>) -> TestResult {
+        let mut compressor = Compressor::new();
+        let _ = compressor.compress(&first);
+
+        let compr = match compressor.compress(&second) {
+            Ok(compr) => compr,
+            Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
+            e => panic!("{:?}", e),
+        };
+        let decompr = decompress(&compr, second.len()).unwrap();
+        TestResult::from_bool(second == decompr)
+    }
+
+    fn decompress_to_vec_round(data: Vec<u8>) -> TestResult {
+        let compr = match compress(&data) {
+            Ok(compr) => compr,
+            Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
+            e => panic!("{:?}", e),
+        };
+        let len = decompressed_len(&compr).unwrap();
+        let decompr = decompress_to_vec(&compr).unwrap();
+        TestResult::from_bool(data.len() == len && data == decompr)
+    }
+
+    #[test]
+    fn qc_decompress_to_vec() {
+        quickcheck(decompress_to_vec_round as fn(_) -> _);
+    }
+
+    fn decompress_to_vec_matches(data: Vec<u8>) -> bool {
+        let limit = data.len() * 2;
+        decompress(&data, limit) == decompress_to_vec_with_limit(&data, limit)
+    }
+
+    #[test]
+    fn qc_decompress_to_vec_matches_decompress() {
+        quickcheck(decompress_to_vec_matches as fn(_) -> _);
+    }
+
+    fn compress_best_round(data: Vec<u8>) -> TestResult {
+        let compr = match compress_best(&data) {
+            Ok(compr) => compr,
+            Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
+            e => panic!("{:?}", e),
+        };
+        let decompr = decompress(&compr, data.len()).unwrap();
+        TestResult::from_bool(data == decompr)
+    }
+
+    #[test]
+    fn qc_compress_best_roundtrip() {
+        quickcheck(compress_best_round as fn(_) -> _);
+    }
+
+    fn compress_with_level_round(data: Vec<u8>) -> bool {
+        let levels = [
+            CompressionLevel::UltraFast,
+            CompressionLevel::VeryFast,
+            CompressionLevel::Normal,
+            CompressionLevel::Best,
+        ];
+
+        levels
+            .into_iter()
+            .all(|level| match compress_with_level(&data, level) {
+                Ok(compr) => decompress(&compr, data.len()).unwrap() == data,
+                Err(LzfError::NoCompressionPossible) => true,
+                e => panic!("{:?}", e),
+            })
+    }
+
+    #[test]
+    fn qc_compress_with_level_roundtrip() {
+        quickcheck(compress_with_level_round as fn(_) -> _);
+    }
+
+    fn compressor_config_round(data: Vec<u8>, hash_log: u32) -> TestResult {
+        let hash_log = CompressorConfig::MIN_HASH_LOG
+            + hash_log % (CompressorConfig::MAX_HASH_LOG - CompressorConfig::MIN_HASH_LOG + 1);
+        let config = CompressorConfig::new(hash_log).unwrap();
+
+        let compr = match Compressor::with_config(config).compress(&data) {
+            Ok(compr) => compr,
+            Err(LzfError::NoCompressionPossible) => return TestResult::discard(),
+            e => panic!("{:?}", e),
+        };
+        let decompr = decompress(&compr, data.len()).unwrap();
+        TestResult::from_bool(data == decompr)
+    }
+
+    #[test]
+    fn qc_compressor_config_roundtrip() {
+        quickcheck(compressor_config_round as fn(_, _) -> _);
+    }
+
+    #[test]
+    fn qc_compressor_reuse() {
+        quickcheck(compressor_reuse_round as fn(_, _) -> _);
+    }
+
+    fn compress_always_round(data: Vec<u8>) -> TestResult {
+        if data.is_empty() {
+            return TestResult::discard();
+        }
+
+        let compr = compress_always(&data).unwrap();
+        if compr.len() > max_compressed_size(data.len()) {
+            return TestResult::failed();
+        }
+        let decompr = decompress(&compr, data.len()).unwrap();
+        TestResult::from_bool(data == decompr)
+    }
+
+    #[test]
+    fn qc_compress_always_roundtrip() {
+        quickcheck(compress_always_round as fn(_) -> _);
+    }
 }
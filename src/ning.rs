@@ -0,0 +1,188 @@
+//! Chunk format of the Java [compress-lzf](https://github.com/ning/compress) library.
+//!
+//! compress-lzf (`com.ning:compress-lzf`) splits the data into chunks of at most
+//! [`MAX_CHUNK_LEN`] bytes, each starting with the signature `ZV` and a type byte:
+//!
+//! * `ZV\0`, 2-byte length, data: the chunk is stored uncompressed.
+//! * `ZV\1`, 2-byte compressed length, 2-byte uncompressed length, data:
+//!   the chunk is lzf-compressed.
+//!
+//! All lengths are stored big-endian.
+//! The layout matches the one of liblzf's `lzf` utility (see [`stream`](crate::stream)),
+//! but the encoder follows the rules of `ChunkEncoder`:
+//! chunks shorter than 16 bytes are never compressed,
+//! compressed chunks are only used if they save at least 3 bytes
+//! and empty input is encoded as a single empty chunk.
+//!
+//! Example:
+//!
+//! ```rust
+//! let data = "aaaaaaaaaaaaaaaaaaaa";
+//!
+//! let encoded = lzf::ning::encode(data.as_bytes());
+//! let decoded = lzf::ning::decode(&encoded).unwrap();
+//! assert_eq!(data.as_bytes(), &decoded[..]);
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use super::frame::{MAGIC, TYPE0_HDR_SIZE, TYPE1_HDR_SIZE, TYPE_COMPRESSED, TYPE_UNCOMPRESSED};
+use super::{decompress_into, Compressor, LzfError, LzfResult};
+
+/// The maximum number of uncompressed bytes stored in a single chunk.
+pub const MAX_CHUNK_LEN: usize = 0xFFFF;
+
+/// Chunks shorter than this are always stored uncompressed.
+const MIN_BLOCK_TO_COMPRESS: usize = 16;
+
+/// Encode the given data into compress-lzf chunks,
+/// like `LZFEncoder.encode` does.
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut compressor = Compressor::new();
+    let mut out = Vec::with_capacity(data.len() + TYPE0_HDR_SIZE);
+    let mut payload = vec![0; MAX_CHUNK_LEN];
+
+    let mut chunks = data.chunks(MAX_CHUNK_LEN);
+    let first = chunks.next().unwrap_or(&[]);
+
+    for chunk in core::iter::once(first).chain(chunks) {
+        let len = chunk.len();
+
+        if len >= MIN_BLOCK_TO_COMPRESS {
+            // Only worth it if we save at least 3 bytes, covering the larger header.
+            let max_len = len - 3;
+            if let Ok(compressed_len) = compressor.compress_into(chunk, &mut payload[..max_len]) {
+                out.extend_from_slice(&MAGIC);
+                out.push(TYPE_COMPRESSED);
+                out.extend_from_slice(&(compressed_len as u16).to_be_bytes());
+                out.extend_from_slice(&(len as u16).to_be_bytes());
+                out.extend_from_slice(&payload[..compressed_len]);
+                continue;
+            }
+        }
+
+        out.extend_from_slice(&MAGIC);
+        out.push(TYPE_UNCOMPRESSED);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+        out.extend_from_slice(chunk);
+    }
+
+    out
+}
+
+/// Decode all compress-lzf chunks in the given data,
+/// like `LZFDecoder.decode` does.
+///
+/// InvalidFrame is returned if a chunk header is invalid or truncated,
+/// or if a compressed chunk does not decompress to its stored length.
+pub fn decode(mut data: &[u8]) -> LzfResult<Vec<u8>> {
+    let mut out = Vec::new();
+
+    while !data.is_empty() {
+        if data.len() < TYPE0_HDR_SIZE || data[..2] != MAGIC {
+            return Err(LzfError::InvalidFrame);
+        }
+
+        let len = u16::from_be_bytes([data[3], data[4]]) as usize;
+        match data[2] {
+            TYPE_UNCOMPRESSED => {
+                let chunk = data
+                    .get(TYPE0_HDR_SIZE..TYPE0_HDR_SIZE + len)
+                    .ok_or(LzfError::InvalidFrame)?;
+                out.extend_from_slice(chunk);
+                data = &data[TYPE0_HDR_SIZE + len..];
+            }
+            TYPE_COMPRESSED => {
+                if data.len() < TYPE1_HDR_SIZE {
+                    return Err(LzfError::InvalidFrame);
+                }
+                let orig_len = u16::from_be_bytes([data[5], data[6]]) as usize;
+                let chunk = data
+                    .get(TYPE1_HDR_SIZE..TYPE1_HDR_SIZE + len)
+                    .ok_or(LzfError::InvalidFrame)?;
+
+                let start = out.len();
+                out.resize(start + orig_len, 0);
+                match decompress_into(chunk, &mut out[start..]) {
+                    Ok(n) if n == orig_len => {}
+                    _ => return Err(LzfError::InvalidFrame),
+                }
+                data = &data[TYPE1_HDR_SIZE + len..];
+            }
+            _ => return Err(LzfError::InvalidFrame),
+        }
+    }
+
+    Ok(out)
+}
+
+// The vectors below are written by hand, following the layout of `LZFChunk`.
+// They only check our own reading of the format.
+// `tests/ning.rs` is meant to decode the output of the Java library itself,
+// but stays ignored until the vectors from `GenerateVectors.java` are checked in.
+
+#[test]
+fn test_decode_vectors() {
+    // A single uncompressed chunk.
+    assert_eq!(b"Hello", &decode(b"ZV\x00\x00\x05Hello").unwrap()[..]);
+
+    // A compressed chunk: literal "ab", then a back-reference of 18 bytes at distance 2.
+    let compressed = b"ZV\x01\x00\x06\x00\x14\x01ab\xe0\x09\x01";
+    assert_eq!(b"abababababababababab", &decode(compressed).unwrap()[..]);
+
+    // Chunks are simply concatenated.
+    let mut both = b"ZV\x00\x00\x05Hello".to_vec();
+    both.extend_from_slice(compressed);
+    assert_eq!(b"Helloabababababababababab", &decode(&both).unwrap()[..]);
+
+    // An empty chunk, as written for empty input.
+    assert!(decode(b"ZV\x00\x00\x00").unwrap().is_empty());
+    assert!(decode(b"").unwrap().is_empty());
+}
+
+#[test]
+fn test_decode_rejects_malformed() {
+    let cases: &[&[u8]] = &[
+        b"ZV\x00\x00",
+        b"ZX\x00\x00\x05Hello",
+        b"ZV\x02\x00\x05Hello",
+        b"ZV\x00\x00\x06Hello",
+        b"ZV\x01\x00\x06\x00",
+        b"ZV\x01\x00\x06\x00\x14\x01ab\xe0\x09",
+        b"ZV\x01\x00\x06\x00\x13\x01ab\xe0\x09\x01",
+        b"ZV\x01\x00\x06\x00\x15\x01ab\xe0\x09\x01",
+    ];
+
+    for case in cases {
+        assert_eq!(LzfError::InvalidFrame, decode(case).unwrap_err());
+    }
+}
+
+#[test]
+fn test_encode_short_chunks_stored() {
+    assert_eq!(b"ZV\x00\x00\x00", &encode(b"")[..]);
+    assert_eq!(b"ZV\x00\x00\x05aaaaa", &encode(b"aaaaa")[..]);
+
+    // Compressible, but below the minimum chunk size.
+    let data = [b'a'; MIN_BLOCK_TO_COMPRESS - 1];
+    assert_eq!(TYPE_UNCOMPRESSED, encode(&data)[2]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_encode_roundtrip() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. ";
+    let data = lorem.repeat(2000);
+
+    let encoded = encode(data.as_bytes());
+    assert!(encoded.len() < data.len());
+    assert_eq!(b"ZV\x01", &encoded[..3]);
+    assert_eq!(data.as_bytes(), &decode(&encoded).unwrap()[..]);
+
+    // The chunk layout is the same as the one of the `lzf` utility.
+    let mut reader = super::stream::Reader::new(&encoded[..]);
+    let mut read = String::new();
+    std::io::Read::read_to_string(&mut reader, &mut read).unwrap();
+    assert_eq!(data, read);
+}
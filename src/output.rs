@@ -0,0 +1,125 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "bytes")]
+use bytes::{BufMut, BytesMut};
+
+/// The buffer the compressors write to.
+///
+/// The control byte of a literal run is skipped when the run starts
+/// and set once its length is known, so everything written so far stays accessible.
+pub(crate) trait Output {
+    /// The number of bytes written (or skipped) so far.
+    fn len(&self) -> usize;
+
+    /// Append a byte.
+    fn push(&mut self, byte: u8);
+
+    /// Append a byte to be set later.
+    fn skip(&mut self);
+
+    /// Overwrite a byte written (or skipped) before.
+    fn set(&mut self, pos: usize, byte: u8);
+
+    /// Drop everything after the first `len` bytes.
+    fn truncate(&mut self, len: usize);
+}
+
+/// Writes into a caller-provided buffer.
+pub(crate) struct SliceOutput<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceOutput<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> Self {
+        SliceOutput { buf, len: 0 }
+    }
+}
+
+impl Output for SliceOutput<'_> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.buf[self.len] = byte;
+        self.len += 1;
+    }
+
+    fn skip(&mut self) {
+        // The compressors might skip one byte past the end of the buffer,
+        // but they check the length before setting it.
+        self.len += 1;
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        self.buf[pos] = byte;
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+/// Writes into the spare capacity of a `Vec`, without initializing it first.
+#[cfg(feature = "alloc")]
+impl Output for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn push(&mut self, byte: u8) {
+        Vec::push(self, byte);
+    }
+
+    fn skip(&mut self) {
+        Vec::push(self, 0);
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        self[pos] = byte;
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+}
+
+/// Appends to a `BytesMut`, leaving its previous content untouched.
+#[cfg(feature = "bytes")]
+pub(crate) struct BytesMutOutput<'a> {
+    buf: &'a mut BytesMut,
+    start: usize,
+}
+
+#[cfg(feature = "bytes")]
+impl<'a> BytesMutOutput<'a> {
+    pub(crate) fn new(buf: &'a mut BytesMut) -> Self {
+        let start = buf.len();
+        BytesMutOutput { buf, start }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Output for BytesMutOutput<'_> {
+    fn len(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.buf.put_u8(byte);
+    }
+
+    fn skip(&mut self) {
+        self.buf.put_u8(0);
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        self.buf[self.start + pos] = byte;
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.buf.truncate(self.start + len);
+    }
+}
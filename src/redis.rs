@@ -0,0 +1,222 @@
+//! LZF-compressed strings as stored in Redis RDB files.
+//!
+//! Redis stores compressible strings as the byte `0xC3`,
+//! followed by the compressed length, the uncompressed length and the lzf-compressed data.
+//! Both lengths use the variable-length integer encoding of RDB files:
+//!
+//! * `00xxxxxx`: a 6-bit length.
+//! * `01xxxxxx xxxxxxxx`: a 14-bit length, big-endian.
+//! * `10000000` followed by 4 bytes: a 32-bit length, big-endian.
+//! * `10000001` followed by 8 bytes: a 64-bit length, big-endian.
+//!
+//! Example:
+//!
+//! ```rust
+//! let data = "aaaaaaaaaaaaaaaaaaaa";
+//!
+//! let mut encoded = Vec::new();
+//! lzf::redis::encode_lzf_string(data.as_bytes(), &mut encoded).unwrap();
+//!
+//! let (decoded, len) = lzf::redis::decode_lzf_string(&encoded).unwrap();
+//! assert_eq!(data.as_bytes(), &decoded[..]);
+//! assert_eq!(encoded.len(), len);
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use super::format::max_decompressed_size;
+use super::{compress_into, decompress_into, LzfError, LzfResult};
+
+/// The type byte of an LZF-compressed string (`RDB_ENCVAL << 6 | RDB_ENC_LZF`).
+pub const LZF_STRING_TYPE: u8 = 0xC3;
+
+const RDB_6BITLEN: u8 = 0;
+const RDB_14BITLEN: u8 = 1;
+const RDB_32BITLEN: u8 = 0x80;
+const RDB_64BITLEN: u8 = 0x81;
+
+/// Append the RDB encoding of `len` to `out`.
+pub fn encode_length(len: u64, out: &mut Vec<u8>) {
+    if len < (1 << 6) {
+        out.push((RDB_6BITLEN << 6) | len as u8);
+    } else if len < (1 << 14) {
+        out.push((RDB_14BITLEN << 6) | (len >> 8) as u8);
+        out.push(len as u8);
+    } else if len <= u32::MAX as u64 {
+        out.push(RDB_32BITLEN);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(RDB_64BITLEN);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// Decode an RDB-encoded length from the start of `data`.
+///
+/// Returns the length and the number of bytes it occupied.
+/// InvalidFrame is returned if the length is truncated
+/// or uses one of the special string encodings.
+pub fn decode_length(data: &[u8]) -> LzfResult<(u64, usize)> {
+    let first = *data.first().ok_or(LzfError::InvalidFrame)?;
+
+    match first >> 6 {
+        RDB_6BITLEN => Ok(((first & 0x3f) as u64, 1)),
+        RDB_14BITLEN => {
+            let second = *data.get(1).ok_or(LzfError::InvalidFrame)?;
+            Ok(((((first & 0x3f) as u64) << 8) | second as u64, 2))
+        }
+        _ if first == RDB_32BITLEN => {
+            let bytes = data.get(1..5).ok_or(LzfError::InvalidFrame)?;
+            Ok((u32::from_be_bytes(bytes.try_into().unwrap()) as u64, 5))
+        }
+        _ if first == RDB_64BITLEN => {
+            let bytes = data.get(1..9).ok_or(LzfError::InvalidFrame)?;
+            Ok((u64::from_be_bytes(bytes.try_into().unwrap()), 9))
+        }
+        _ => Err(LzfError::InvalidFrame),
+    }
+}
+
+/// Compress `data` and append it as an RDB LZF string to `out`.
+///
+/// Just like Redis, this insists on saving at least 4 bytes.
+/// Otherwise NoCompressionPossible is returned, `out` is left untouched
+/// and the string should be stored as is.
+pub fn encode_lzf_string(data: &[u8], out: &mut Vec<u8>) -> LzfResult<()> {
+    if data.len() <= 4 {
+        return Err(LzfError::NoCompressionPossible);
+    }
+
+    let mut compressed = vec![0; data.len() - 4];
+    let compressed_len = compress_into(data, &mut compressed)?;
+
+    out.push(LZF_STRING_TYPE);
+    encode_length(compressed_len as u64, out);
+    encode_length(data.len() as u64, out);
+    out.extend_from_slice(&compressed[..compressed_len]);
+
+    Ok(())
+}
+
+/// Decode an RDB LZF string, starting with its type byte, from the start of `data`.
+///
+/// Returns the decompressed string and the number of bytes the encoded string occupied.
+/// InvalidFrame is returned if the type byte or one of the lengths is invalid,
+/// the data is truncated or it does not decompress to the stored length.
+/// An uncompressed length the compressed data can't possibly expand to is rejected
+/// before any memory is allocated for it.
+pub fn decode_lzf_string(data: &[u8]) -> LzfResult<(Vec<u8>, usize)> {
+    if data.first() != Some(&LZF_STRING_TYPE) {
+        return Err(LzfError::InvalidFrame);
+    }
+    let mut pos = 1;
+
+    let (compressed_len, len) = decode_length(&data[pos..])?;
+    pos += len;
+    let (out_len, len) = decode_length(&data[pos..])?;
+    pos += len;
+
+    let compressed_len = usize::try_from(compressed_len).map_err(|_| LzfError::InvalidFrame)?;
+    let out_len = usize::try_from(out_len).map_err(|_| LzfError::InvalidFrame)?;
+
+    let compressed = data
+        .get(pos..)
+        .and_then(|rest| rest.get(..compressed_len))
+        .ok_or(LzfError::InvalidFrame)?;
+    pos += compressed_len;
+
+    // Don't allocate more than the compressed data can possibly expand to.
+    if out_len > max_decompressed_size(compressed_len) {
+        return Err(LzfError::InvalidFrame);
+    }
+
+    let mut out = vec![0; out_len];
+    match decompress_into(compressed, &mut out) {
+        Ok(len) if len == out_len => Ok((out, pos)),
+        _ => Err(LzfError::InvalidFrame),
+    }
+}
+
+#[test]
+fn test_length_encoding() {
+    let cases: &[(u64, &[u8])] = &[
+        (0, &[0x00]),
+        (63, &[0x3f]),
+        (64, &[0x40, 0x40]),
+        (16383, &[0x7f, 0xff]),
+        (16384, &[0x80, 0x00, 0x00, 0x40, 0x00]),
+        (u32::MAX as u64, &[0x80, 0xff, 0xff, 0xff, 0xff]),
+        (u32::MAX as u64 + 1, &[0x81, 0, 0, 0, 0x01, 0, 0, 0, 0]),
+    ];
+
+    for &(len, encoded) in cases {
+        let mut out = Vec::new();
+        encode_length(len, &mut out);
+        assert_eq!(encoded, &out[..]);
+        assert_eq!((len, encoded.len()), decode_length(encoded).unwrap());
+    }
+}
+
+#[test]
+fn test_decode_length_rejects_malformed() {
+    let cases: &[&[u8]] = &[&[], &[0x40], &[0x80, 0, 0, 0], &[0x81, 0], &[0xc0], &[0x82]];
+
+    for case in cases {
+        assert_eq!(LzfError::InvalidFrame, decode_length(case).unwrap_err());
+    }
+}
+
+#[test]
+fn test_decode_rdb_string() {
+    // The data of the `easily_compressible` regression test, followed by the next opcode.
+    let encoded = [
+        0xc3, 0x09, 0x40, 0xc8, 1, 97, 97, 224, 187, 0, 1, 97, 97, 0xff,
+    ];
+
+    let (decoded, len) = decode_lzf_string(&encoded).unwrap();
+    assert_eq!(vec![97; 200], decoded);
+    assert_eq!(13, len);
+}
+
+#[test]
+fn test_decode_rdb_string_rejects_malformed() {
+    let cases: &[&[u8]] = &[
+        &[],
+        &[0xc0, 0x09, 0x40, 0xc8, 1, 97, 97, 224, 187, 0, 1, 97, 97],
+        &[0xc3, 0x09, 0x40],
+        &[0xc3, 0x09, 0x40, 0xc8, 1, 97, 97, 224, 187, 0, 1, 97],
+        &[0xc3, 0x09, 0x40, 0xc7, 1, 97, 97, 224, 187, 0, 1, 97, 97],
+        &[0xc3, 0x09, 0x40, 0xc9, 1, 97, 97, 224, 187, 0, 1, 97, 97],
+        // Lengths far beyond what the compressed data expands to.
+        &[
+            0xc3, 0x01, 0x81, 0x00, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+        ],
+        &[0xc3, 0x01, 0x80, 0xff, 0xff, 0xff, 0xff, 0x00],
+    ];
+
+    for case in cases {
+        assert_eq!(LzfError::InvalidFrame, decode_lzf_string(case).unwrap_err());
+    }
+}
+
+#[test]
+fn test_encode_rdb_string() {
+    let mut out = vec![0xff];
+    assert_eq!(
+        LzfError::NoCompressionPossible,
+        encode_lzf_string(b"aaaa", &mut out).unwrap_err()
+    );
+    assert_eq!(
+        LzfError::NoCompressionPossible,
+        encode_lzf_string(b"abcdefgh", &mut out).unwrap_err()
+    );
+    assert_eq!(vec![0xff], out);
+
+    let data = vec![97; 200];
+    encode_lzf_string(&data, &mut out).unwrap();
+    assert_eq!(LZF_STRING_TYPE, out[1]);
+
+    let (decoded, len) = decode_lzf_string(&out[1..]).unwrap();
+    assert_eq!(data, decoded);
+    assert_eq!(out.len() - 1, len);
+}
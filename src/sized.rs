@@ -0,0 +1,171 @@
+use alloc::{vec, vec::Vec};
+
+use super::format::max_decompressed_size;
+use super::{compress_into, decompress_into, LzfError, LzfResult};
+
+/// Flag in the header, set if the payload is stored uncompressed.
+const STORED: u64 = 1;
+
+/// Compress the given data and prefix it with its original length.
+///
+/// The result can be decompressed with [`decompress_with_size`]
+/// without knowing the original length up front.
+/// The header is a varint (LEB128) of `len << 1 | stored`.
+/// If the data can't be compressed, it is stored uncompressed instead,
+/// so this never fails.
+///
+/// Example:
+///
+/// ```rust
+/// let data = "aaaaaaaaa";
+/// let compressed = lzf::compress_with_size(data.as_bytes());
+/// let decompressed = lzf::decompress_with_size(&compressed).unwrap();
+/// assert_eq!(data.as_bytes(), &decompressed[..]);
+/// ```
+pub fn compress_with_size(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(10 + data.len());
+
+    write_varint((data.len() as u64) << 1, &mut out);
+    let header_len = out.len();
+
+    out.resize(header_len + data.len(), 0);
+    match compress_into(data, &mut out[header_len..]) {
+        Ok(len) => out.truncate(header_len + len),
+        Err(_) => {
+            out.clear();
+            write_varint((data.len() as u64) << 1 | STORED, &mut out);
+            out.extend_from_slice(data);
+        }
+    }
+
+    out
+}
+
+/// Decompress data produced by [`compress_with_size`].
+///
+/// InvalidFrame is returned if the header is invalid,
+/// or if the payload does not match the stored length.
+/// A stored length the payload can't possibly decompress to is rejected
+/// before any memory is allocated for it.
+pub fn decompress_with_size(data: &[u8]) -> LzfResult<Vec<u8>> {
+    let (header, header_len) = read_varint(data)?;
+    let payload = &data[header_len..];
+    let out_len = usize::try_from(header >> 1).map_err(|_| LzfError::InvalidFrame)?;
+
+    if header & STORED != 0 {
+        if payload.len() != out_len {
+            return Err(LzfError::InvalidFrame);
+        }
+        return Ok(payload.to_vec());
+    }
+    if out_len > max_decompressed_size(payload.len()) {
+        return Err(LzfError::InvalidFrame);
+    }
+
+    let mut out = vec![0; out_len];
+    match decompress_into(payload, &mut out) {
+        Ok(len) if len == out_len => Ok(out),
+        Ok(_) | Err(LzfError::OutputLimitExceeded) => Err(LzfError::InvalidFrame),
+        Err(err) => Err(err),
+    }
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read a varint from the start of `data`,
+/// returning its value and the number of bytes it occupied.
+fn read_varint(data: &[u8]) -> LzfResult<(u64, usize)> {
+    let mut value = 0;
+
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        let bits = (byte & 0x7f) as u64;
+        if i == 9 && bits > 1 {
+            return Err(LzfError::InvalidFrame);
+        }
+
+        value |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    Err(LzfError::InvalidFrame)
+}
+
+#[test]
+fn test_varint() {
+    let cases: &[(u64, &[u8])] = &[
+        (0, &[0x00]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x01]),
+        (300, &[0xac, 0x02]),
+        (
+            u64::MAX,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ),
+    ];
+
+    for &(value, encoded) in cases {
+        let mut out = Vec::new();
+        write_varint(value, &mut out);
+        assert_eq!(encoded, &out[..]);
+        assert_eq!((value, encoded.len()), read_varint(encoded).unwrap());
+    }
+
+    assert!(read_varint(&[]).is_err());
+    assert!(read_varint(&[0x80, 0x80]).is_err());
+    assert!(read_varint(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]).is_err());
+}
+
+#[test]
+fn test_with_size_roundtrip() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At \
+                 vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, \
+                 no sea takimata sanctus est Lorem ipsum dolor sit amet.";
+
+    for data in ["", "a", "foo", lorem] {
+        let compressed = compress_with_size(data.as_bytes());
+        let decompressed = decompress_with_size(&compressed).unwrap();
+        assert_eq!(data.as_bytes(), &decompressed[..]);
+    }
+
+    let compressed = compress_with_size(lorem.as_bytes());
+    assert!(compressed.len() < lorem.len());
+    assert_eq!(b"\x07foo", &compress_with_size(b"foo")[..]);
+}
+
+#[test]
+fn test_decompress_with_size_rejects_malformed() {
+    let cases: &[&[u8]] = &[
+        b"",
+        b"\x07fo",
+        b"\x07fooo",
+        b"\x12\x01aa\xe0\xbb\x00\x01aa",
+        b"\x92\x03\x01aa\xe0\xbb\x00\x01aa",
+    ];
+
+    for case in cases {
+        assert_eq!(
+            LzfError::InvalidFrame,
+            decompress_with_size(case).unwrap_err()
+        );
+    }
+
+    // A huge length must not be allocated.
+    assert_eq!(
+        LzfError::InvalidFrame,
+        decompress_with_size(b"\xfe\xff\xff\xff\xff\xff\xff\x3f\x00").unwrap_err()
+    );
+
+    assert_eq!(
+        vec![97; 200],
+        decompress_with_size(b"\x90\x03\x01aa\xe0\xbb\x00\x01aa").unwrap()
+    );
+}
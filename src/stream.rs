@@ -0,0 +1,636 @@
+//! Streaming compression in the block format of liblzf's `lzf` utility.
+//!
+//! The data is split into blocks of at most [`BLOCK_SIZE`] bytes.
+//! Every block starts with the magic `ZV`, followed by the block type:
+//!
+//! * `ZV\0`, 2-byte length, data: the data is stored uncompressed.
+//! * `ZV\1`, 2-byte compressed length, 2-byte uncompressed length, data:
+//!   the data is lzf-compressed.
+//!
+//! All lengths are stored big-endian.
+//! Files written by the `lzf` command-line utility can be read by this module and vice versa.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::io::{Read, Write};
+//!
+//! let mut writer = lzf::stream::Writer::new(Vec::new());
+//! writer.write_all("aaaaaaaaaaaaaaaaaaaa".as_bytes()).unwrap();
+//! let framed = writer.finish().unwrap();
+//!
+//! let mut reader = lzf::stream::Reader::new(&framed[..]);
+//! let mut data = String::new();
+//! reader.read_to_string(&mut data).unwrap();
+//! assert_eq!("aaaaaaaaaaaaaaaaaaaa", data);
+//! ```
+
+use std::io::{self, BufRead, Read, Write};
+
+use super::frame::{MAGIC, TYPE0_HDR_SIZE, TYPE1_HDR_SIZE, TYPE_COMPRESSED, TYPE_UNCOMPRESSED};
+use super::{decompress_into, Compressor, LzfError};
+
+/// The maximum number of uncompressed bytes stored in a single block.
+pub const BLOCK_SIZE: usize = 64 * 1024 - 1;
+
+/// Compresses data written to it and writes the framed blocks to the underlying writer.
+///
+/// Data is buffered until a full block is available.
+/// Call [`finish`](Writer::finish) (or [`flush`](Write::flush)) to write out the last partial block.
+/// Dropping the writer writes the last block as well, but ignores any errors.
+///
+/// Example:
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// let mut writer = lzf::stream::Writer::new(Vec::new());
+/// writer.write_all("aaaaaaaaaaaaaaaaaaaa".as_bytes()).unwrap();
+/// let framed = writer.finish().unwrap();
+/// assert_eq!(b"ZV\x01", &framed[..3]);
+/// ```
+pub struct Writer<W: Write> {
+    inner: Option<W>,
+    compressor: Compressor,
+    block: Vec<u8>,
+    out: Vec<u8>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Create a new writer, writing the compressed blocks to `inner`.
+    pub fn new(inner: W) -> Writer<W> {
+        Writer {
+            inner: Some(inner),
+            compressor: Compressor::new(),
+            block: Vec::with_capacity(BLOCK_SIZE),
+            out: vec![0; TYPE1_HDR_SIZE + BLOCK_SIZE],
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing to it directly will corrupt the compressed stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Write out the last block and return the underlying writer.
+    ///
+    /// If that fails, the last block is lost.
+    pub fn finish(mut self) -> io::Result<W> {
+        let result = self.write_block();
+        // Don't let `drop` write the block again after a partial write.
+        let mut inner = self.inner.take().unwrap();
+        result?;
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        let len = encode_block(&mut self.compressor, &self.block, &mut self.out)?;
+        self.inner.as_mut().unwrap().write_all(&self.out[..len])?;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.block.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_block();
+        }
+    }
+}
+
+/// Reads framed blocks from the underlying reader and yields the decompressed data.
+///
+/// Malformed input (a truncated block, an unknown block type or a block
+/// not decompressing to its stored length) results in an error
+/// of kind `InvalidData` or `UnexpectedEof`.
+pub struct Reader<R: Read> {
+    inner: R,
+    compressed: Vec<u8>,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Reader<R> {
+    /// Create a new reader, reading the compressed blocks from `inner`.
+    pub fn new(inner: R) -> Reader<R> {
+        Reader {
+            inner,
+            compressed: Vec::new(),
+            block: Vec::with_capacity(BLOCK_SIZE),
+            pos: 0,
+        }
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly will corrupt the compressed stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Return the underlying reader.
+    ///
+    /// Any data of the current block not yet read is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the next block into the internal buffer.
+    ///
+    /// Returns `false` if the end of the stream was reached.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let header = match read_header(&mut self.inner)? {
+            Some(header) => header,
+            None => return Ok(false),
+        };
+
+        self.block.clear();
+        self.pos = 0;
+
+        match header {
+            Header::Uncompressed { len } => {
+                self.block.resize(len, 0);
+                if read_full(&mut self.inner, &mut self.block)? != len {
+                    return Err(unexpected_eof("truncated uncompressed block"));
+                }
+            }
+            Header::Compressed { len, original_len } => {
+                self.compressed.resize(len, 0);
+                if read_full(&mut self.inner, &mut self.compressed)? != len {
+                    return Err(unexpected_eof("truncated compressed block"));
+                }
+
+                decompress_block(&self.compressed, original_len, &mut self.block)?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Read> BufRead for Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Loop, because a block might be empty.
+        while self.pos == self.block.len() {
+            if !self.read_block()? {
+                break;
+            }
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.block.len());
+    }
+}
+
+/// Check the blocks read from `reader` without decompressing them.
+///
+/// Compressed blocks are validated with [`verify`](crate::verify),
+/// which is a lot cheaper than decompressing them.
+/// On success the total length of the decompressed data is returned.
+/// Otherwise the error describes the offset of the first invalid block in the stream.
+///
+/// Example:
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// let mut writer = lzf::stream::Writer::new(Vec::new());
+/// writer.write_all("aaaaaaaaaaaaaaaaaaaa".as_bytes()).unwrap();
+/// let framed = writer.finish().unwrap();
+///
+/// assert_eq!(20, lzf::stream::verify(&framed[..]).unwrap());
+/// assert!(lzf::stream::verify(&framed[..framed.len() - 1]).is_err());
+/// ```
+pub fn verify<R: Read>(mut reader: R) -> io::Result<u64> {
+    let mut compressed = Vec::new();
+    let mut offset = 0;
+    let mut total = 0;
+
+    let at = |offset: u64, err: io::Error| {
+        io::Error::new(err.kind(), format!("block at offset {}: {}", offset, err))
+    };
+
+    while let Some(header) = read_header(&mut reader).map_err(|err| at(offset, err))? {
+        let (len, original_len, hdr_size) = match header {
+            Header::Uncompressed { len } => (len, len, TYPE0_HDR_SIZE),
+            Header::Compressed { len, original_len } => (len, original_len, TYPE1_HDR_SIZE),
+        };
+
+        compressed.resize(len, 0);
+        if read_full(&mut reader, &mut compressed)? != len {
+            return Err(at(offset, unexpected_eof("truncated block")));
+        }
+
+        if let Header::Compressed { .. } = header {
+            super::verify(&compressed, original_len).map_err(|err| at(offset, block_error(err)))?;
+        }
+
+        offset += (hdr_size + len) as u64;
+        total += original_len as u64;
+    }
+
+    Ok(total)
+}
+
+/// Compress `block` and write it as a single framed block to `out`.
+///
+/// `out` must hold at least `TYPE1_HDR_SIZE + block.len()` bytes.
+/// Returns the length of the framed block.
+pub(crate) fn encode_block(
+    compressor: &mut Compressor,
+    block: &[u8],
+    out: &mut [u8],
+) -> io::Result<usize> {
+    let us = block.len();
+
+    // Just like the `lzf` utility, insist on saving at least a few bytes,
+    // otherwise the larger header eats up the gain.
+    let max_len = if us > 4 { us - 4 } else { us };
+    let payload = &mut out[TYPE1_HDR_SIZE..TYPE1_HDR_SIZE + max_len];
+
+    match compressor.compress_into(block, payload) {
+        Ok(cs) => {
+            let header = &mut out[..TYPE1_HDR_SIZE];
+            header[..2].copy_from_slice(&MAGIC);
+            header[2] = TYPE_COMPRESSED;
+            header[3..5].copy_from_slice(&(cs as u16).to_be_bytes());
+            header[5..7].copy_from_slice(&(us as u16).to_be_bytes());
+            Ok(TYPE1_HDR_SIZE + cs)
+        }
+        Err(LzfError::NoCompressionPossible) => {
+            let header = &mut out[..TYPE0_HDR_SIZE];
+            header[..2].copy_from_slice(&MAGIC);
+            header[2] = TYPE_UNCOMPRESSED;
+            header[3..5].copy_from_slice(&(us as u16).to_be_bytes());
+            out[TYPE0_HDR_SIZE..TYPE0_HDR_SIZE + us].copy_from_slice(block);
+            Ok(TYPE0_HDR_SIZE + us)
+        }
+        Err(err) => Err(io::Error::other(err.to_string())),
+    }
+}
+
+/// Decode the first block of `input` into `block`, if `input` holds all of it.
+///
+/// Returns the length of the framed block,
+/// or `None` if more input is needed to decode it.
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub(crate) fn decode_block(input: &[u8], block: &mut Vec<u8>) -> io::Result<Option<usize>> {
+    if input.len() < TYPE0_HDR_SIZE || (input[2] == TYPE_COMPRESSED && input.len() < TYPE1_HDR_SIZE)
+    {
+        return Ok(None);
+    }
+
+    let mut rest = input;
+    let header = match read_header(&mut rest)? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let len = match header {
+        Header::Uncompressed { len } | Header::Compressed { len, .. } => len,
+    };
+    if rest.len() < len {
+        return Ok(None);
+    }
+
+    block.clear();
+    match header {
+        Header::Uncompressed { len } => block.extend_from_slice(&rest[..len]),
+        Header::Compressed { len, original_len } => {
+            decompress_block(&rest[..len], original_len, block)?
+        }
+    }
+
+    Ok(Some(input.len() - rest.len() + len))
+}
+
+/// Decompress the payload of a compressed block into `block`.
+fn decompress_block(compressed: &[u8], original_len: usize, block: &mut Vec<u8>) -> io::Result<()> {
+    block.resize(original_len, 0);
+    match decompress_into(compressed, block) {
+        Ok(len) if len == original_len => Ok(()),
+        Ok(_) => Err(block_error(LzfError::InputTooShort)),
+        Err(err) => Err(block_error(err)),
+    }
+}
+
+/// The header of a block.
+enum Header {
+    Uncompressed { len: usize },
+    Compressed { len: usize, original_len: usize },
+}
+
+/// Read the header of the next block.
+///
+/// Returns `None` if the end of the stream was reached.
+fn read_header<R: Read>(reader: &mut R) -> io::Result<Option<Header>> {
+    let mut header = [0; TYPE1_HDR_SIZE];
+    match read_full(reader, &mut header[..TYPE0_HDR_SIZE])? {
+        0 => return Ok(None),
+        TYPE0_HDR_SIZE => {}
+        _ => return Err(unexpected_eof("truncated block header")),
+    }
+
+    if header[..2] != MAGIC {
+        return Err(invalid_data("invalid block header, magic not found"));
+    }
+
+    let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+    match header[2] {
+        TYPE_UNCOMPRESSED => Ok(Some(Header::Uncompressed { len })),
+        TYPE_COMPRESSED => {
+            if read_full(reader, &mut header[TYPE0_HDR_SIZE..])? != TYPE1_HDR_SIZE - TYPE0_HDR_SIZE
+            {
+                return Err(unexpected_eof("truncated block header"));
+            }
+            let original_len = u16::from_be_bytes([header[5], header[6]]) as usize;
+            Ok(Some(Header::Compressed { len, original_len }))
+        }
+        ty => Err(invalid_data(&format!("unknown block type {}", ty))),
+    }
+}
+
+/// Describe why a compressed block does not decompress to its stored length.
+fn block_error(err: LzfError) -> io::Error {
+    match err {
+        LzfError::InputTooShort => {
+            invalid_data("compressed block is shorter than its stored length")
+        }
+        LzfError::OutputLimitExceeded => {
+            invalid_data("compressed block is longer than its stored length")
+        }
+        err => invalid_data(&format!("invalid compressed block: {}", err)),
+    }
+}
+
+/// Read until `buf` is full or the end of the stream is reached.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn unexpected_eof(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, msg)
+}
+
+#[cfg(test)]
+fn split_blocks(mut data: &[u8]) -> Vec<Vec<u8>> {
+    let mut blocks = Vec::new();
+    while !data.is_empty() {
+        assert_eq!(MAGIC, data[..2]);
+        let len = u16::from_be_bytes([data[3], data[4]]) as usize;
+        match data[2] {
+            TYPE_UNCOMPRESSED => {
+                blocks.push(data[TYPE0_HDR_SIZE..TYPE0_HDR_SIZE + len].to_vec());
+                data = &data[TYPE0_HDR_SIZE + len..];
+            }
+            TYPE_COMPRESSED => {
+                let us = u16::from_be_bytes([data[5], data[6]]) as usize;
+                let payload = &data[TYPE1_HDR_SIZE..TYPE1_HDR_SIZE + len];
+                blocks.push(super::decompress(payload, us).unwrap());
+                data = &data[TYPE1_HDR_SIZE + len..];
+            }
+            ty => panic!("unknown block type {}", ty),
+        }
+    }
+    blocks
+}
+
+#[test]
+fn test_writer_stores_incompressible() {
+    let mut writer = Writer::new(Vec::new());
+    writer.write_all("foo".as_bytes()).unwrap();
+    let framed = writer.finish().unwrap();
+
+    assert_eq!(b"ZV\x00\x00\x03foo", &framed[..]);
+}
+
+#[test]
+fn test_writer_empty() {
+    let writer = Writer::new(Vec::new());
+    assert!(writer.finish().unwrap().is_empty());
+}
+
+#[test]
+fn test_writer_multiple_blocks() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. ";
+    let data = lorem.repeat(2000);
+
+    let mut writer = Writer::new(Vec::new());
+    for chunk in data.as_bytes().chunks(1000) {
+        writer.write_all(chunk).unwrap();
+    }
+    let framed = writer.finish().unwrap();
+    assert!(framed.len() < data.len());
+
+    let blocks = split_blocks(&framed);
+    assert_eq!(data.len().div_ceil(BLOCK_SIZE), blocks.len());
+    assert!(blocks[..blocks.len() - 1]
+        .iter()
+        .all(|block| block.len() == BLOCK_SIZE));
+    assert_eq!(data.as_bytes(), &blocks.concat()[..]);
+}
+
+#[test]
+fn test_writer_flush_ends_block() {
+    let mut writer = Writer::new(Vec::new());
+    writer.write_all(&[1; 100]).unwrap();
+    writer.flush().unwrap();
+    writer.write_all(&[2; 100]).unwrap();
+    let framed = writer.finish().unwrap();
+
+    assert_eq!(vec![vec![1; 100], vec![2; 100]], split_blocks(&framed));
+}
+
+#[test]
+fn test_writer_finish_error_writes_once() {
+    /// Accepts the first few bytes, then fails once.
+    struct Flaky<'a> {
+        out: &'a mut Vec<u8>,
+        failed: bool,
+    }
+
+    impl Write for Flaky<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.out.len() < 3 || self.failed {
+                let len = buf.len().min(3);
+                self.out.extend_from_slice(&buf[..len]);
+                return Ok(len);
+            }
+            self.failed = true;
+            Err(io::Error::other("flaky"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut writer = Writer::new(Flaky {
+        out: &mut out,
+        failed: false,
+    });
+    writer.write_all(&[1; 100]).unwrap();
+    assert!(writer.finish().is_err());
+
+    // Only the part written before the error, nothing duplicated by `drop`.
+    assert_eq!(b"ZV\x01", &out[..]);
+}
+
+#[cfg(test)]
+fn read_all(framed: &[u8]) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    Reader::new(framed).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[test]
+fn test_reader_roundtrip() {
+    let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod \
+                 tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. ";
+    let data = lorem.repeat(2000);
+
+    let mut writer = Writer::new(Vec::new());
+    writer.write_all(data.as_bytes()).unwrap();
+    writer.flush().unwrap();
+    writer.write_all("foo".as_bytes()).unwrap();
+    let framed = writer.finish().unwrap();
+
+    assert_eq!(
+        data + "foo",
+        String::from_utf8(read_all(&framed).unwrap()).unwrap()
+    );
+}
+
+#[test]
+fn test_reader_empty_blocks() {
+    assert!(read_all(&[]).unwrap().is_empty());
+    assert_eq!(
+        b"foo",
+        &read_all(b"ZV\x00\x00\x00ZV\x00\x00\x03foo").unwrap()[..]
+    );
+}
+
+#[test]
+fn test_reader_rejects_malformed() {
+    let cases: &[(&[u8], io::ErrorKind)] = &[
+        (b"ZV\x00\x00", io::ErrorKind::UnexpectedEof),
+        (b"ZV\x01\x00\x02\x00", io::ErrorKind::UnexpectedEof),
+        (b"ZV\x00\x00\x04foo", io::ErrorKind::UnexpectedEof),
+        (
+            b"ZV\x01\x00\x04\x00\x03\x02fo",
+            io::ErrorKind::UnexpectedEof,
+        ),
+        (b"ZX\x00\x00\x03foo", io::ErrorKind::InvalidData),
+        (b"ZV\x02\x00\x03foo", io::ErrorKind::InvalidData),
+        // Decompresses to "foo", but claims to be longer or shorter.
+        (b"ZV\x01\x00\x04\x00\x04\x02foo", io::ErrorKind::InvalidData),
+        (b"ZV\x01\x00\x04\x00\x02\x02foo", io::ErrorKind::InvalidData),
+        // Back-reference pointing before the start of the block.
+        (
+            b"ZV\x01\x00\x02\x00\x03\x20\x00",
+            io::ErrorKind::InvalidData,
+        ),
+    ];
+
+    for (framed, kind) in cases {
+        assert_eq!(*kind, read_all(framed).unwrap_err().kind(), "{:?}", framed);
+        assert_eq!(
+            *kind,
+            verify(&framed[..]).unwrap_err().kind(),
+            "{:?}",
+            framed
+        );
+    }
+}
+
+#[test]
+fn test_verify_reports_offset() {
+    let mut framed = b"ZV\x00\x00\x03fooZV\x01\x00\x09\x00\xc8".to_vec();
+    framed.extend_from_slice(&[1, 97, 97, 224, 187, 0, 1, 97, 97]);
+    assert_eq!(203, verify(&framed[..]).unwrap());
+
+    // Truncate the back-reference of the second block.
+    framed[12] = 0x04;
+    framed.truncate(19);
+    assert_eq!(
+        "block at offset 8: invalid compressed block: the given data is corrupted: \
+         truncated back-reference at offset 3 (control byte 0xe0)",
+        verify(&framed[..]).unwrap_err().to_string()
+    );
+}
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+#[test]
+fn test_decode_block_needs_whole_block() {
+    let framed = b"ZV\x01\x00\x04\x00\x03\x02fooZV\x00\x00\x03bar";
+    let mut block = Vec::new();
+
+    for len in 0..11 {
+        assert_eq!(None, decode_block(&framed[..len], &mut block).unwrap());
+    }
+    assert_eq!(Some(11), decode_block(framed, &mut block).unwrap());
+    assert_eq!(b"foo", &block[..]);
+    assert_eq!(Some(8), decode_block(&framed[11..], &mut block).unwrap());
+    assert_eq!(b"bar", &block[..]);
+}
@@ -0,0 +1,212 @@
+//! Run the `lzf` utility on files in a temporary directory.
+//!
+//! `tests/data/cli/liblzf.bin.lzf` was written by liblzf's own `lzf` utility
+//! (`lzf -c -b 1024 < liblzf.bin > liblzf.bin.lzf`),
+//! and has both compressed and uncompressed blocks.
+
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const LOREM: &[u8] = b"Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy \
+                       eirmod tempor invidunt ut labore et dolore magna aliquyam erat, sed diam \
+                       voluptua. Lorem ipsum dolor sit amet, consetetur sadipscing elitr.";
+
+/// A fresh directory for a single test, removed again when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("lzf-cli-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.0.join(file)
+    }
+
+    fn write(&self, file: &str, data: &[u8]) -> PathBuf {
+        let path = self.path(file);
+        fs::write(&path, data).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn fixture(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data/cli")
+        .join(file)
+}
+
+fn lzf(args: &[&str], files: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lzf"))
+        .args(args)
+        .args(files)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+fn lzf_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lzf"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_compress_decompress_file() {
+    let dir = TempDir::new("roundtrip");
+    let input = dir.write("lorem.txt", LOREM);
+    let compressed = dir.path("lorem.txt.lzf");
+
+    let output = lzf(&["c"], &[&input]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!input.exists());
+    assert!(fs::metadata(&compressed).unwrap().len() < LOREM.len() as u64);
+
+    let output = lzf(&["d"], &[&compressed]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!compressed.exists());
+    assert_eq!(LOREM, &fs::read(&input).unwrap()[..]);
+}
+
+#[test]
+fn test_suffix() {
+    let dir = TempDir::new("suffix");
+    let compressed = dir.write("lorem.lzf", LOREM);
+    let plain = dir.write("lorem.txt", LOREM);
+
+    let output = lzf(&["c"], &[&compressed]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(stderr(&output).contains("already has .lzf suffix"));
+    assert!(!dir.path("lorem.lzf.lzf").exists());
+
+    let output = lzf(&["d"], &[&plain]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(stderr(&output).contains("unknown suffix, expected .lzf"));
+
+    let output = lzf(&["d"], &[&dir.write(".lzf", b"")]);
+    assert_eq!(Some(1), output.status.code());
+
+    // Nothing was touched.
+    assert_eq!(LOREM, &fs::read(&compressed).unwrap()[..]);
+    assert_eq!(LOREM, &fs::read(&plain).unwrap()[..]);
+}
+
+#[test]
+fn test_keep_input() {
+    let dir = TempDir::new("keep");
+    let input = dir.write("lorem.txt", LOREM);
+    let compressed = dir.path("lorem.txt.lzf");
+
+    let output = lzf(&["c", "-k"], &[&input]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(input.exists() && compressed.exists());
+
+    fs::remove_file(&input).unwrap();
+    let output = lzf(&["d", "-k"], &[&compressed]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(compressed.exists());
+    assert_eq!(LOREM, &fs::read(&input).unwrap()[..]);
+}
+
+#[test]
+fn test_refuse_overwrite() {
+    let dir = TempDir::new("overwrite");
+    let input = dir.write("lorem.txt", LOREM);
+    let existing = dir.write("lorem.txt.lzf", b"precious");
+
+    let output = lzf(&["c"], &[&input]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(stderr(&output).contains("already exists, use -f to overwrite it"));
+    assert!(input.exists());
+    assert_eq!(b"precious", &fs::read(&existing).unwrap()[..]);
+
+    let output = lzf(&["c", "-f"], &[&input]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!input.exists());
+
+    let data = fs::read(&existing).unwrap();
+    let mut decompressed = Vec::new();
+    std::io::copy(&mut lzf::stream::Reader::new(&data[..]), &mut decompressed).unwrap();
+    assert_eq!(LOREM, &decompressed[..]);
+}
+
+#[test]
+fn test_remove_partial_output() {
+    let dir = TempDir::new("partial");
+    let mut data = fs::read(fixture("liblzf.bin.lzf")).unwrap();
+    data.truncate(data.len() - 10);
+    let truncated = dir.write("truncated.lzf", &data);
+
+    let output = lzf(&["d"], &[&truncated]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(!dir.path("truncated").exists());
+    assert!(truncated.exists());
+
+    let output = lzf(&["t"], &[&truncated]);
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn test_stdin_stdout() {
+    let compressed = lzf_stdin(&["c"], LOREM);
+    assert!(compressed.status.success(), "{}", stderr(&compressed));
+    assert_eq!(b"ZV", &compressed.stdout[..2]);
+
+    let decompressed = lzf_stdin(&["d", "-"], &compressed.stdout);
+    assert!(decompressed.status.success(), "{}", stderr(&decompressed));
+    assert_eq!(LOREM, &decompressed.stdout[..]);
+
+    let output = lzf_stdin(&["d"], b"not lzf");
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn test_stdout_keeps_input() {
+    let dir = TempDir::new("stdout");
+    let input = dir.write("lorem.txt", LOREM);
+
+    let compressed = lzf(&["c", "-c"], &[&input]);
+    assert!(compressed.status.success(), "{}", stderr(&compressed));
+    assert!(input.exists());
+    assert!(!dir.path("lorem.txt.lzf").exists());
+
+    let decompressed = lzf_stdin(&["d"], &compressed.stdout);
+    assert_eq!(LOREM, &decompressed.stdout[..]);
+}
+
+#[test]
+fn test_liblzf_fixture() {
+    let data = fs::read(fixture("liblzf.bin")).unwrap();
+    let compressed = fixture("liblzf.bin.lzf");
+
+    let output = lzf(&["t"], &[&compressed]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = lzf(&["d", "-c"], &[&compressed]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(data, output.stdout);
+}
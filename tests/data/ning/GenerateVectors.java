@@ -0,0 +1,37 @@
+// Writes the test vectors decoded by `tests/ning.rs` with the Java compress-lzf library.
+//
+// Usage, from this directory:
+//
+//     javac -cp compress-lzf-1.1.2.jar GenerateVectors.java
+//     java -cp compress-lzf-1.1.2.jar:. GenerateVectors
+//
+// Every vector is written as `<name>.bin` (the input) and `<name>.bin.lzf` (its encoding).
+
+import com.ning.compress.lzf.LZFEncoder;
+
+import java.io.IOException;
+import java.nio.charset.StandardCharsets;
+import java.nio.file.Files;
+import java.nio.file.Paths;
+
+public class GenerateVectors {
+    public static void main(String[] args) throws IOException {
+        // Shorter than 16 bytes, so ChunkEncoder stores it as a single uncompressed chunk.
+        write("stored", "Hello, World".getBytes(StandardCharsets.US_ASCII));
+
+        // Three chunks, the last one partial, all of them compressed.
+        String lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy "
+                + "eirmod tempor invidunt ut labore et dolore magna aliquyam erat, sed diam "
+                + "voluptua. ";
+        StringBuilder multi = new StringBuilder();
+        for (int i = 0; i < 2000; i++) {
+            multi.append(lorem);
+        }
+        write("multi", multi.toString().getBytes(StandardCharsets.US_ASCII));
+    }
+
+    private static void write(String name, byte[] data) throws IOException {
+        Files.write(Paths.get(name + ".bin"), data);
+        Files.write(Paths.get(name + ".bin.lzf"), LZFEncoder.encode(data));
+    }
+}
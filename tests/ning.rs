@@ -0,0 +1,32 @@
+//! Decode chunks written by the Java compress-lzf library itself.
+//!
+//! The vectors in `tests/data/ning` are generated by `GenerateVectors.java`
+//! in the same directory, see there for how to run it.
+
+#![cfg(feature = "alloc")]
+
+use std::fs;
+use std::path::Path;
+
+fn vector(name: &str) -> (Vec<u8>, Vec<u8>) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/ning");
+    let read =
+        |file: String| fs::read(dir.join(&file)).unwrap_or_else(|err| panic!("{}: {}", file, err));
+    (
+        read(format!("{}.bin", name)),
+        read(format!("{}.bin.lzf", name)),
+    )
+}
+
+#[test]
+#[ignore = "the vectors from GenerateVectors.java are not checked in yet"]
+fn test_decode_java_vectors() {
+    let (data, encoded) = vector("stored");
+    assert_eq!(b"ZV\x00", &encoded[..3]);
+    assert_eq!(data, lzf::ning::decode(&encoded).unwrap());
+
+    let (data, encoded) = vector("multi");
+    assert!(data.len() > 2 * lzf::ning::MAX_CHUNK_LEN);
+    assert_eq!(b"ZV\x01", &encoded[..3]);
+    assert_eq!(data, lzf::ning::decode(&encoded).unwrap());
+}